@@ -2,8 +2,8 @@
 
 use bevy::{color::palettes::tailwind::*, prelude::*, window::PrimaryWindow};
 use bevy_pathfinding::{
-    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, grid::Grid, utils,
-    BevyPathfindingPlugin,
+    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, flowfield::Goal,
+    grid::Grid, utils, BevyPathfindingPlugin,
 };
 
 const CELL_SIZE: f32 = 10.0; // size of each cell in the grid
@@ -122,7 +122,7 @@ fn spawn_obstacles(
             Mesh3d(meshes.add(Cuboid::from_size(size))),
             MeshMaterial3d(materials.add(StandardMaterial::from_color(GRAY_400))),
             Transform::from_translation(pos),
-            Obstacle(size.xz()), // ADD THIS!
+            ObstacleFromMesh, // ADD THIS! - Can also be set by hand with `Obstacle::new(ObstacleShape::Rectangle(size.xz()))`
             Name::new("Obstacle"),
         )
     };
@@ -184,7 +184,8 @@ fn set_unit_destination(
         // create a flowfield and assign the units and destination position it it
         cmds.trigger(InitializeFlowFieldEv {
             entities: units,
-            destination_pos,
+            destination: Goal::Position(destination_pos),
+            ..default()
         });
     }
 }
@@ -0,0 +1,77 @@
+// This example benchmarks the integration field on weighted maps the size of the stress tests.
+// It compares the priority-queue Dijkstra used by the flowfields against the FIFO queue it replaced,
+// and checks that both produce identical integration fields.
+//
+// Both sides are timed on the same prepared costs: `FlowField::integrate` runs on a cost field that is
+// built once, and the FIFO queue on the terrain costs. The goal covers two cells, so the line of sight
+// pass that `integrate` runs for a single goal cell is not timed either.
+//
+// Run it in release mode for meaningful numbers: cargo run --release --example integration_benchmark
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_pathfinding::{cost::Cost, flowfield::FlowField};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+use common::{costs, fifo_integration_field, weighted_grid};
+
+const MAP_GRIDS: [IVec2; 2] = [IVec2::new(150, 150), IVec2::new(300, 300)]; // stress_test and stress_test2
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    for size in MAP_GRIDS {
+        let mut grid = weighted_grid(size, 0x9E37_79B9);
+        let goals = [size / 2, size / 2 + IVec2::X];
+        for goal in goals {
+            grid.set_terrain_cost(goal, Cost::ONE);
+        }
+
+        // priority-queue Dijkstra
+        let mut prepared = FlowField::new(size, Vec::new(), 0.0, Vec3::ZERO);
+        prepared.create_cost_field(&grid);
+        let mut dijkstra_time = Duration::ZERO;
+        let mut ff = prepared.clone();
+        for _ in 0..ITERATIONS {
+            ff.clone_from(&prepared);
+            let start = Instant::now();
+            ff.integrate(&goals);
+            dijkstra_time += start.elapsed();
+        }
+        let dijkstra_time = dijkstra_time / ITERATIONS;
+
+        // FIFO reference
+        let costs = costs(&grid);
+        let start = Instant::now();
+        let mut fifo = (Vec::new(), 0);
+        for _ in 0..ITERATIONS {
+            fifo = fifo_integration_field(&costs, &goals);
+        }
+        let fifo_time = start.elapsed() / ITERATIONS;
+        let (fifo_field, fifo_visits) = fifo;
+
+        let identical = ff
+            .grid
+            .iter()
+            .zip(fifo_field.iter())
+            .all(|(row, fifo_row)| {
+                row.iter()
+                    .zip(fifo_row.iter())
+                    .all(|(cell, best_cost)| cell.best_cost == *best_cost)
+            });
+
+        let cell_count = (size.x * size.y) as f32;
+        println!("{}x{} grid", size.x, size.y);
+        println!(
+            "  dijkstra: {:?}, at most one expansion per cell",
+            dijkstra_time
+        );
+        println!(
+            "  fifo:     {:?}, {:.2} expansions per cell",
+            fifo_time,
+            fifo_visits as f32 / cell_count
+        );
+        println!("  identical fields: {}", identical);
+    }
+}
@@ -3,8 +3,8 @@
 
 use bevy::{color::palettes::tailwind::*, prelude::*, window::PrimaryWindow};
 use bevy_pathfinding::{
-    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, grid::Grid, utils,
-    BevyPathfindingPlugin,
+    components::*, debug::resources::DbgOptions, events::InitializeFlowFieldEv, flowfield::Goal,
+    grid::Grid, utils, BevyPathfindingPlugin,
 };
 
 const CELL_SIZE: f32 = 10.0; // size of each cell in the grid
@@ -162,7 +162,8 @@ fn set_unit_destination(
         // create a flowfield and assign the units and destination position it it
         cmds.trigger(InitializeFlowFieldEv {
             entities: units,
-            destination_pos,
+            destination: Goal::Position(destination_pos),
+            ..default()
         });
     }
 }
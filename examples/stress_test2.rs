@@ -8,6 +8,7 @@ use bevy_pathfinding::{
     components::*,
     debug::resources::{BoidUpdater, DbgOptions},
     events::InitializeFlowFieldEv,
+    flowfield::Goal,
     grid::Grid,
     utils, BevyPathfindingPlugin,
 };
@@ -189,7 +190,8 @@ fn set_unit_destination(
         // create a flowfield and assign the units and destination position it it
         cmds.trigger(InitializeFlowFieldEv {
             entities: units,
-            destination_pos,
+            destination: Goal::Position(destination_pos),
+            ..default()
         });
     }
 }
@@ -1,24 +1,34 @@
 use bevy::prelude::*;
-use std::u16;
-
-use crate::grid_direction::GridDirection;
+use crate::{
+    components::TerrainType, cost::Cost, flowfield::UNREACHED, grid_direction::GridDirection,
+};
 
 #[derive(Clone, Default, Copy, Debug, PartialEq, Reflect)]
 pub struct Cell {
-    pub best_cost: u16,
+    /// The integration cost from the cell to the destination. `UNREACHED` if the destination can't be reached.
+    pub best_cost: u32,
     pub best_direction: GridDirection,
-    pub cost: u8,
+    pub cost: Cost,
+    /// The continuous direction of the flow through the cell, normalized. Zero where there is no flow.
+    pub flow: Vec2,
     pub idx: IVec2,
+    /// True if the cell has a straight, unobstructed line to the destination. The flow of these cells points
+    /// straight at the destination.
+    pub line_of_sight: bool,
+    pub terrain: TerrainType,
     pub world_pos: Vec3,
 }
 
 impl Cell {
     pub fn new(world_position: Vec3, grid_idx: IVec2) -> Self {
         Cell {
-            best_cost: u16::MAX,
+            best_cost: UNREACHED,
             best_direction: GridDirection::None,
-            cost: 1,
+            cost: Cost::ONE,
+            flow: Vec2::ZERO,
             idx: grid_idx,
+            line_of_sight: false,
+            terrain: TerrainType::GROUND,
             world_pos: world_position,
         }
     }
@@ -27,15 +37,25 @@ impl Cell {
         self.idx.y * columns as i32 + self.idx.x
     }
 
+    // blocked cells have no digits
     pub fn cost_to_vec(&self) -> Vec<u32> {
-        self.cost
+        let Some(cost) = self.cost.value() else {
+            return Vec::new();
+        };
+
+        cost
         .to_string()
         .chars()
         .filter_map(|c| c.to_digit(10))
         .collect()
     }
 
+    // unreached cells have no digits
     pub fn best_cost_to_vec(&self) -> Vec<u32> {
+        if self.best_cost == UNREACHED {
+            return Vec::new();
+        }
+
         self.best_cost
         .to_string()
         .chars()
@@ -43,15 +63,73 @@ impl Cell {
         .collect()
     }
 
-    pub fn increase_cost(&mut self, amount: u8) {
-        if self.cost == u8::MAX {
-            return;
+    /// Raises the cost of a walkable cell. The cost saturates at `Cost::MAX`, so this never blocks the cell.
+    pub fn increase_cost(&mut self, amount: u16) {
+        if let Cost::Walkable(cost) = self.cost {
+            self.cost = Cost::Walkable(cost.saturating_add(amount));
         }
+    }
 
-        if let Some(new_cost) = self.cost.checked_add(amount) {
-            self.cost = new_cost;
-        } else {
-            self.cost = u8::MAX;
+    /// Lowers the cost of a walkable cell. The cost saturates at `Cost::MIN`, and blocked cells stay blocked.
+    pub fn decrease_cost(&mut self, amount: u16) {
+        if let Cost::Walkable(cost) = self.cost {
+            self.cost = Cost::Walkable(cost.saturating_sub(amount).max(1));
         }
     }
 }
+
+/// The layers that make up the cost of a single cell. The effective cost stored in `Cell::cost` is
+/// always derived from these, so an obstacle or modifier can be removed without losing the terrain
+/// cost underneath it.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct CostLayers {
+    /// The base terrain cost of the cell. `Cost::Blocked` makes the terrain itself impassable.
+    pub terrain: Cost,
+    /// The number of obstacles currently covering the cell.
+    pub obstacles: u16,
+    /// Extra cost from the falloff bands of nearby obstacles.
+    pub obstacle_falloff: u32,
+    /// Extra cost added on top of the terrain cost with `Grid::add_cost_modifier`. Can be negative.
+    pub modifier: i32,
+    /// The sum of the amounts added by the `CostModifier`s covering the cell. Can be negative.
+    pub effect_amount: i64,
+    /// The product of the multipliers of the `CostModifier`s covering the cell.
+    pub multiplier: f32,
+}
+
+impl Default for CostLayers {
+    fn default() -> Self {
+        CostLayers {
+            terrain: Cost::ONE,
+            obstacles: 0,
+            obstacle_falloff: 0,
+            modifier: 0,
+            effect_amount: 0,
+            multiplier: 1.0,
+        }
+    }
+}
+
+impl CostLayers {
+    /// The cost of the cell with every layer applied.
+    pub fn effective_cost(&self) -> Cost {
+        self.cost_with_terrain(self.terrain)
+    }
+
+    /// The cost of the cell with every layer applied, using a different terrain cost.
+    pub fn cost_with_terrain(&self, terrain: Cost) -> Cost {
+        let Cost::Walkable(terrain) = terrain else {
+            return Cost::Blocked;
+        };
+
+        if self.obstacles > 0 {
+            return Cost::Blocked;
+        }
+
+        // modifiers can make a cell cheaper or more expensive, but never impassable
+        let terrain = (terrain as f32 * self.multiplier).round() as i64;
+        Cost::walkable_clamped(
+            terrain + self.modifier as i64 + self.effect_amount + self.obstacle_falloff as i64,
+        )
+    }
+}
@@ -1,7 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
 
+use crate::cost::Cost;
+
 /// A marker component for the map base. Insert this into your base map entity.
 #[derive(Component)]
 pub struct MapBase;
@@ -10,15 +12,187 @@ pub struct MapBase;
 #[derive(Component)]
 pub struct GameCamera;
 
-/// Destination marker. This is dynamically added to every boid entity when the flowfield is initialized.
+/// Destination marker. This is dynamically added to every boid entity once its flowfield is ready.
 #[derive(Component)]
 pub struct Destination;
 
 /// Obstacle marker. Insert this into any entity that you want to be considered an obstacle by the flowfield(s).
-/// # Parameters
-/// - `Vec2`: The size of the obstacles mesh. Only the x and z values are used.
+/// The shape is placed on the costfield using the entity's `GlobalTransform`, including its rotation and scale.
+///
+/// # Example
+/// ```
+/// Obstacle::new(ObstacleShape::Rectangle(Vec2::new(35.0, 35.0)));
+///
+/// // keep units a cell away from the walls, and make the next two cells more expensive
+/// Obstacle {
+///     padding: 5.0,
+///     falloff: 20.0,
+///     falloff_cost: 20,
+///     ..Obstacle::new(ObstacleShape::Circle(20.0))
+/// };
+/// ```
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Obstacle {
+    /// The footprint of the obstacle on the x and z axes.
+    pub shape: ObstacleShape,
+    /// Impassable space around the shape, in world units. A cell is blocked if its center is within this distance.
+    pub padding: f32,
+    /// The width of the band around the padding where the cell costs are raised, in world units.
+    pub falloff: f32,
+    /// The cost added at the inner edge of the falloff band, in the fixed-point units of `Cost`. It fades out
+    /// towards the outer edge.
+    pub falloff_cost: u16,
+}
+
+impl Obstacle {
+    /// Creates a new obstacle with the given shape.
+    ///
+    /// # Parameters
+    /// - `shape`: The footprint of the obstacle on the x and z axes.
+    pub fn new(shape: ObstacleShape) -> Self {
+        Self {
+            shape,
+            padding: 0.0,
+            falloff: 0.0,
+            falloff_cost: 0,
+        }
+    }
+}
+
+/// Insert this into an entity to compute its `Obstacle` footprint from its mesh `Aabb`, including the meshes of
+/// its descendants (like the children of a glTF scene). Once every mesh has loaded, the footprint is stored in the
+/// entity's `Obstacle` (inserted if missing) and this marker is removed. Any other settings of an existing
+/// `Obstacle` are kept.
+///
+/// # Example
+/// ```
+/// cmds.spawn((SceneRoot(asset_server.load("building.glb#Scene0")), ObstacleFromMesh));
+/// ```
 #[derive(Component, Default)]
-pub struct Obstacle(pub Vec2);
+pub struct ObstacleFromMesh;
+
+/// Changes the cost of every cell under its shape, like a cheap road or an expensive swamp. Insert this into any
+/// entity, and it is applied to (and removed from) the costfield the same way as an `Obstacle`.
+///
+/// # Example
+/// ```
+/// // roads are cheaper to travel on
+/// CostModifier::new(ObstacleShape::Rectangle(Vec2::new(10.0, 200.0)), CostEffect::Multiply(0.5));
+///
+/// // swamps are more expensive
+/// CostModifier::new(ObstacleShape::Circle(40.0), CostEffect::Add(50));
+/// ```
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct CostModifier {
+    /// The area affected by the modifier on the x and z axes.
+    pub shape: ObstacleShape,
+    /// How the modifier changes the cost of each cell.
+    pub effect: CostEffect,
+}
+
+impl CostModifier {
+    /// Creates a new cost modifier.
+    ///
+    /// # Parameters
+    /// - `shape`: The area affected by the modifier on the x and z axes.
+    /// - `effect`: How the modifier changes the cost of each cell.
+    pub fn new(shape: ObstacleShape, effect: CostEffect) -> Self {
+        Self { shape, effect }
+    }
+}
+
+/// How a `CostModifier` changes the cost of a cell. Multipliers are applied to the terrain cost before any
+/// amounts are added. Modifiers never make a cell impassable, use an `Obstacle` for that.
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum CostEffect {
+    /// Adds an amount to the cost, in the fixed-point units of `Cost`. Use a negative amount to make cells cheaper.
+    Add(i32),
+    /// Multiplies the terrain cost. Must be greater than 0.0.
+    Multiply(f32),
+}
+
+impl Default for CostEffect {
+    fn default() -> Self {
+        CostEffect::Add(0)
+    }
+}
+
+/// The footprint of an obstacle, relative to its entity. The `x` value of each `Vec2` is used for
+/// the x axis and the `y` value is used for the z axis.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObstacleShape {
+    /// A rectangle with the given size.
+    Rectangle(Vec2),
+    /// A circle with the given radius.
+    Circle(f32),
+    /// A capsule aligned with the z axis.
+    /// # Parameters
+    /// - `radius`: The radius of the capsule.
+    /// - `half_length`: Half the length of the straight section between the two rounded ends.
+    Capsule { radius: f32, half_length: f32 },
+    /// A convex polygon made of the given vertices, in either winding order.
+    ConvexPolygon(Vec<Vec2>),
+    /// Several shapes combined into one. Each shape is placed with its own offset and rotation.
+    Compound(Vec<(Isometry2d, ObstacleShape)>),
+}
+
+impl Default for ObstacleShape {
+    fn default() -> Self {
+        ObstacleShape::Rectangle(Vec2::ZERO)
+    }
+}
+
+/// A tag describing the kind of terrain a cell is made of, like water or hills. The meaning of each value is up to
+/// you, and `MovementProfile`s decide what each terrain type costs for a unit.
+///
+/// # Example
+/// ```
+/// const WATER: TerrainType = TerrainType(1);
+///
+/// grid.set_terrain_type(IVec2::new(3, 4), WATER);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub struct TerrainType(pub u8);
+
+impl TerrainType {
+    /// The terrain type every cell starts with.
+    pub const GROUND: TerrainType = TerrainType(0);
+}
+
+/// Maps terrain types to costs for a type of unit. Insert this into your units to override the grid's terrain
+/// costs for them. Units with different profiles that are ordered together are split into separate flowfields.
+///
+/// # Example
+/// ```
+/// const WATER: TerrainType = TerrainType(1);
+/// const HILLS: TerrainType = TerrainType(2);
+///
+/// // tanks can't cross water and struggle up hills
+/// MovementProfile::new([(WATER, Cost::Blocked), (HILLS, Cost::from_f32(4.0))]);
+/// ```
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct MovementProfile {
+    /// The cost of each terrain type. `Cost::Blocked` makes the terrain impassable. Terrain types that are missing
+    /// use the grid's terrain cost.
+    pub costs: HashMap<TerrainType, Cost>,
+}
+
+impl MovementProfile {
+    /// Creates a new movement profile.
+    ///
+    /// # Parameters
+    /// - `costs`: The cost of each terrain type. `Cost::Blocked` makes the terrain impassable.
+    pub fn new(costs: impl IntoIterator<Item = (TerrainType, Cost)>) -> Self {
+        Self {
+            costs: costs.into_iter().collect(),
+        }
+    }
+
+    /// Returns the cost of a terrain type, or `None` if the grid's terrain cost should be used.
+    pub fn cost(&self, terrain: TerrainType) -> Option<Cost> {
+        self.costs.get(&terrain).copied()
+    }
+}
 
 /// Boid component with settings. Insert this into your entities that you want to control with the flowfields. Use the Boid::new() to use custom settings.
 #[derive(Component, Debug)]
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+/// The cost of moving through a cell.
+///
+/// Walkable costs are fixed-point numbers with `Cost::SCALE` units per 1.0, so open ground (`Cost::ONE`) is
+/// `Cost::Walkable(10)`, a road at half the cost is `Cost::Walkable(5)` and a swamp at 3.5 times the cost is
+/// `Cost::Walkable(35)`. Use `Cost::from_f32` to skip the conversion.
+///
+/// # Example
+/// ```
+/// let road = Cost::from_f32(0.5);
+/// assert_eq!(road, Cost::Walkable(5));
+/// assert!(Cost::Blocked.is_blocked());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum Cost {
+    /// A walkable cell with a fixed-point cost of at least 1.
+    Walkable(u16),
+    /// A cell that can't be walked through.
+    Blocked,
+}
+
+impl Cost {
+    /// The number of fixed-point units in a cost of 1.0.
+    pub const SCALE: u16 = 10;
+    /// The cost of open ground. Every cell starts with this cost.
+    pub const ONE: Cost = Cost::Walkable(Cost::SCALE);
+    /// The cheapest walkable cost.
+    pub const MIN: Cost = Cost::Walkable(1);
+    /// The most expensive walkable cost.
+    pub const MAX: Cost = Cost::Walkable(u16::MAX);
+
+    /// Creates a walkable cost from a decimal cost, where 1.0 is open ground. Clamped to the walkable range.
+    pub fn from_f32(cost: f32) -> Self {
+        Cost::walkable_clamped((cost * Cost::SCALE as f32).round() as i64)
+    }
+
+    /// Creates a walkable cost from a fixed-point value. Clamped to the walkable range.
+    pub fn walkable_clamped(value: i64) -> Self {
+        Cost::Walkable(value.clamp(1, u16::MAX as i64) as u16)
+    }
+
+    /// Returns true if the cell can't be walked through.
+    pub fn is_blocked(&self) -> bool {
+        *self == Cost::Blocked
+    }
+
+    /// Returns the fixed-point value of a walkable cost, or `None` if it is blocked.
+    pub fn value(&self) -> Option<u16> {
+        match self {
+            Cost::Walkable(value) => Some(*value),
+            Cost::Blocked => None,
+        }
+    }
+
+    /// Returns the decimal value of a walkable cost, where 1.0 is open ground, or `None` if it is blocked.
+    pub fn to_f32(&self) -> Option<f32> {
+        self.value().map(|value| value as f32 / Cost::SCALE as f32)
+    }
+}
+
+impl Default for Cost {
+    fn default() -> Self {
+        Cost::ONE
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use super::components::*;
 use super::resources::*;
 use crate::*;
+use flowfield::UNREACHED;
 use grid::Grid;
 
 const BASE_SCALE: f32 = 0.2;
@@ -176,7 +177,7 @@ pub fn draw_flowfield(
 
     for cell_row in active_dbg_ff.grid.iter() {
         for cell in cell_row.iter() {
-            let is_destination_cell = active_dbg_ff.destination_cell.idx == cell.idx;
+            let is_destination_cell = active_dbg_ff.goal_set.contains(&cell.idx);
             let id = cell.idx_to_id(grid.grid.len());
 
             let mut instance_data = Vec::new();
@@ -186,7 +187,7 @@ pub fn draw_flowfield(
             let rotation = flatten * heading;
 
             if !is_destination_cell {
-                if cell.cost == u8::MAX {
+                if cell.cost.is_blocked() || cell.best_cost == UNREACHED {
                     instance_data.push(debug::shader::InstanceData {
                         position: cell.world_pos + offset,
                         scale: marker_scale,
@@ -282,6 +283,19 @@ fn draw_costfield(
             let id = cell.idx_to_id(grid.grid.len());
 
             let mut instance_data = Vec::new();
+
+            // blocked cells have no cost to draw, mark them with an X instead
+            if cell.cost.is_blocked() {
+                instance_data.push(debug::shader::InstanceData {
+                    position: cell.world_pos + base_offset,
+                    scale: 0.5,
+                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    color: [1.0, 1.0, 1.0, 1.0],
+                    texture: -2,
+                    id,
+                });
+            }
+
             for (i, &digit) in digits_vec.iter().enumerate() {
                 let mut offset = base_offset;
                 offset.x += x_offset + i as f32 * digit_spacing;
@@ -364,6 +378,19 @@ fn draw_integration_field(
             let id = cell.idx_to_id(grid.grid.len());
 
             let mut instance_data = Vec::new();
+
+            // the destination can't be reached from this cell, mark it with an X instead
+            if cell.best_cost == UNREACHED {
+                instance_data.push(debug::shader::InstanceData {
+                    position: cell.world_pos + base_offset,
+                    scale: 0.5,
+                    rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2).into(),
+                    color: [1.0, 1.0, 1.0, 1.0],
+                    texture: -2,
+                    id,
+                });
+            }
+
             for (i, &digit) in digits_vec.iter().enumerate() {
                 let mut offset = base_offset;
                 offset.x += x_offset + i as f32 * digit_spacing;
@@ -1,8 +1,11 @@
 use bevy::prelude::*;
 
-use crate::flowfield::FlowField;
+use crate::{
+    flowfield::{FlowField, Goal},
+    grid::IntegrationMode,
+};
 
-/// Event to initialize the flowfield. This event is used to set the destination position for the flowfield and the entities that will be affected by it.
+/// Event to initialize the flowfield. This event is used to set the destination of the flowfield and the entities that will be affected by it.
 ///
 /// # Example
 ///
@@ -56,14 +59,45 @@ use crate::flowfield::FlowField;
 ///     // create a flowfield and assign the units and destination position it it
 ///     cmds.trigger(InitializeFlowFieldEv {
 ///         entities: units,
-///         destination_pos,
+///         destination: Goal::Position(destination_pos),
+///         ..default()
 ///     });
 /// }
 /// ```
-#[derive(Event)]
+#[derive(Event, Default)]
 pub struct InitializeFlowFieldEv {
     pub entities: Vec<Entity>,
-    pub destination_pos: Vec3,
+    /// Where the units should go. With several goals, each unit heads for the closest one.
+    pub destination: Goal,
+    /// The radius of the largest unit in the group. Cells with less clearance than this are treated as blocked
+    /// by the flowfield. Use 0.0 to ignore clearance.
+    pub unit_radius: f32,
+    /// Overrides the grid's `integration_mode` for this flowfield. Use `None` to keep the grid's mode.
+    pub integration_mode: Option<IntegrationMode>,
+}
+
+/// Triggered when some goals of a flowfield are blocked or can't be reached by its units. The flowfield only leads
+/// to the goals they can reach, or to the closest cell they can reach if there are none. Flowfields with a movement
+/// profile, a unit radius or eikonal integration only find out once they are built, so it is triggered then.
+#[derive(Event)]
+pub struct DestinationAdjustedEv {
+    /// The units of the flowfield.
+    pub entities: Vec<Entity>,
+    /// The destination from the `InitializeFlowFieldEv`.
+    pub requested: Goal,
+    /// The centers of the cells the flowfield leads to instead.
+    pub goal_positions: Vec<Vec3>,
+}
+
+/// Triggered when some of the units in an `InitializeFlowFieldEv` can't reach the destination at all, like units
+/// on another island. They are left out of the flowfield and keep idling. Like `DestinationAdjustedEv`, it is
+/// triggered once the flowfield is built unless the regions of the grid could tell right away.
+#[derive(Event)]
+pub struct UnreachableDestinationEv {
+    /// The units that can't reach the destination.
+    pub entities: Vec<Entity>,
+    /// The destination from the `InitializeFlowFieldEv`.
+    pub destination: Goal,
 }
 
 #[derive(Event)]
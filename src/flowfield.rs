@@ -1,18 +1,42 @@
+use bevy::ecs::entity::Entities;
 use bevy::prelude::*;
+use bevy::tasks::{futures::check_ready, AsyncComputeTaskPool, Task};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::f32::consts::SQRT_2;
 
 use crate::components::*;
 use crate::events::*;
-use crate::{cell::*, grid::Grid, grid_direction::GridDirection, utils};
+use crate::{
+    cell::*,
+    cost::Cost,
+    grid::{compute_clearance, CornerCutting, Grid, IntegrationMode},
+    grid_direction::GridDirection,
+    resources::{FlowFieldCache, FlowFieldKey},
+    utils,
+};
+
+/// The integration cost of a cell the destination can't be reached from.
+pub const UNREACHED: u32 = u32::MAX;
+
+/// The largest integration cost of a reachable cell. Costs saturate here so they never overflow into `UNREACHED`.
+pub const MAX_INTEGRATION_COST: u32 = u32::MAX - 1;
 
 pub struct FlowfieldPlugin;
 
 impl Plugin for FlowfieldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, flowfield_group_stop_system)
-            .add_observer(update_fields)
-            .add_observer(initialize_flowfield);
+        app.add_systems(
+            Update,
+            (poll_pending_flowfields, flowfield_group_stop_system),
+        )
+        .init_resource::<FlowFieldOrders>()
+        .add_observer(update_fields)
+        .add_observer(initialize_flowfield);
     }
 }
 
@@ -20,16 +44,167 @@ impl Plugin for FlowfieldPlugin {
 #[derive(Component)]
 pub struct DestinationRadius(pub u32);
 
+/// Where a flowfield leads to. With several goals, each unit heads for whichever goal is cheapest to reach from
+/// where it is. A unit arrives once it enters any of the goal cells, so an area goal spreads a big group over the
+/// whole area instead of having it fight over a single cell.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    /// A single world position.
+    Position(Vec3),
+    /// Several world positions, like every drop-off depot of a harvester.
+    Positions(Vec<Vec3>),
+    /// Several cells of the grid.
+    Cells(Vec<IVec2>),
+    /// Every cell whose center is within `radius` of `center` on the XZ plane.
+    Circle { center: Vec3, radius: f32 },
+    /// Every cell whose center is inside the rectangle of `size` (X and Z) around `center`.
+    Rectangle { center: Vec3, size: Vec2 },
+}
+
+impl Default for Goal {
+    fn default() -> Self {
+        Goal::Position(Vec3::ZERO)
+    }
+}
+
+impl From<Vec3> for Goal {
+    fn from(pos: Vec3) -> Self {
+        Goal::Position(pos)
+    }
+}
+
+impl Goal {
+    /// Returns the grid cells of the goal, without duplicates. Cells outside of the grid are left out.
+    pub fn cells(&self, grid: &Grid) -> Vec<IVec2> {
+        let mut cells: Vec<IVec2> = match self {
+            Goal::Position(pos) => vec![grid.get_cell_from_world_position(*pos).idx],
+            Goal::Positions(positions) => positions
+                .iter()
+                .map(|pos| grid.get_cell_from_world_position(*pos).idx)
+                .collect(),
+            Goal::Cells(cells) => cells
+                .iter()
+                .copied()
+                .filter(|idx| idx.cmpge(IVec2::ZERO).all() && idx.cmplt(grid.size).all())
+                .collect(),
+            Goal::Circle { center, radius } => {
+                let radius2 = radius * radius;
+                Goal::cells_in_area(grid, *center, Vec2::splat(*radius * 2.0), |offset| {
+                    offset.length_squared() <= radius2
+                })
+            }
+            Goal::Rectangle { center, size } => {
+                Goal::cells_in_area(grid, *center, *size, |offset| {
+                    offset.abs().cmple(*size / 2.0).all()
+                })
+            }
+        };
+
+        let mut unique = HashSet::with_capacity(cells.len());
+        cells.retain(|idx| unique.insert(*idx));
+        cells
+    }
+
+    // the cells within the bounds of `size` around `center` whose centers pass the `contains` check on their XZ
+    // offset from `center`. The cell under `center` is always included, so an area smaller than a cell still has a goal
+    fn cells_in_area(
+        grid: &Grid,
+        center: Vec3,
+        size: Vec2,
+        contains: impl Fn(Vec2) -> bool,
+    ) -> Vec<IVec2> {
+        let center_cell = grid.get_cell_from_world_position(center).idx;
+        let half_extent = (size / 2.0 / grid.cell_diameter).ceil().as_ivec2() + IVec2::ONE;
+        let min = (center_cell - half_extent).max(IVec2::ZERO);
+        let max = (center_cell + half_extent).min(grid.size - IVec2::ONE);
+
+        let mut cells = vec![center_cell];
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let world_pos = grid.grid[y as usize][x as usize].world_pos;
+                let offset = Vec2::new(world_pos.x - center.x, world_pos.z - center.z);
+                if contains(offset) {
+                    cells.push(IVec2::new(x, y));
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+/// The flow at a point, returned by `FlowField::sample_flow`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlowSample {
+    /// The direction to move in on the x and z axes, normalized. Zero if there is nowhere to go.
+    pub direction: Vec2,
+    /// How much the surrounding cells agree on the direction, from 0.0 to 1.0. Low near obstacles, where the
+    /// flow splits, and where there is no flow at all.
+    pub confidence: f32,
+    /// True if the point is inside a blocked or unreachable cell.
+    pub at_obstacle: bool,
+}
+
+/// A flowfield that is still being built on the `AsyncComputeTaskPool`. Its units keep their previous flowfield, or
+/// idle, until it is ready.
+#[derive(Component)]
+pub struct PendingFlowField {
+    /// The units the flowfield is for. Units that are given a newer order before it is ready are removed, and the
+    /// flowfield is discarded once none are left.
+    pub units: Vec<Entity>,
+    /// The id of the order the flowfield was requested by. A later order for any of its units supersedes it for
+    /// those units, even if the later order is still pending itself.
+    pub order: u64,
+    // the destination the units were ordered to, for the events sent once the task found out which goals they can
+    // reach
+    destination: Goal,
+    // the version of the costfield it is built from, for its cache key
+    version: u64,
+    // the cells whose cost changed while it was being built
+    dirty: Option<IRect>,
+    // the reachability is only found by the task if the regions couldn't tell when the flowfield was ordered
+    task: Task<(FlowField, Option<Reachability>)>,
+}
+
+// the goals the units of a flowfield head for, and the units that can't reach any of the requested goals
+struct Reachability {
+    goals: Vec<IVec2>,
+    // true if the goals are not the requested ones
+    adjusted: bool,
+    stranded: Vec<Entity>,
+}
+
+// the latest order of every unit waiting for a flowfield. Resources are updated right away, unlike the entities
+// spawned by an order, so orders in the same frame still see each other
+#[derive(Resource, Default)]
+struct FlowFieldOrders {
+    next: u64,
+    latest: HashMap<Entity, u64>,
+}
+
 #[derive(Component, Clone, Default, PartialEq)]
 pub struct FlowField {
     pub arrived: bool,
+    /// Whether diagonal moves may pass the corners of blocked cells.
+    pub corner_cutting: CornerCutting,
     pub destination_grid_size: IVec2,
+    /// The first goal cell of the flowfield.
     pub destination_cell: Cell,
     pub destination_radius: f32,
+    /// The cells the flowfield leads to.
+    pub goal_cells: Vec<IVec2>,
+    /// DONT SET. The goal cells, to look up whether a cell is one of them.
+    pub goal_set: HashSet<IVec2>,
     pub grid: Vec<Vec<Cell>>,
+    /// The algorithm used to build the integration field.
+    pub integration_mode: IntegrationMode,
     pub offset: Vec3,
+    /// The movement profile shared by every unit in the flowfield.
+    pub profile: Option<MovementProfile>,
     pub size: IVec2,
     pub steering_map: HashMap<Entity, Vec3>,
+    /// The radius of the largest unit using the flowfield. Cells with less clearance are treated as blocked.
+    pub unit_radius: f32,
     pub units: Vec<Entity>,
 }
 
@@ -49,11 +224,36 @@ impl FlowField {
     }
 
     pub fn create_flowfield(&mut self) {
+        self.derive_flow(IRect::from_corners(IVec2::ZERO, self.size - IVec2::ONE));
+    }
+
+    // derives the best direction and flow of the cells in the rectangle from the integration field
+    fn derive_flow(&mut self, rect: IRect) {
+        let destination_idx = self.destination_cell.idx;
         let grid_size_y = self.size.y as usize;
         let grid_size_x = self.size.x as usize;
 
-        for y in 0..grid_size_y {
-            for x in 0..grid_size_x {
+        // eikonal fields already hold their gradient, dijkstra fields derive it from the integration costs of the
+        // rectangle and the cells around it
+        let window = IRect::from_corners(
+            (rect.min - IVec2::ONE).max(IVec2::ZERO),
+            (rect.max + IVec2::ONE).min(self.size - IVec2::ONE),
+        );
+        let costs = (self.integration_mode == IntegrationMode::Dijkstra).then(|| {
+            (window.min.y..=window.max.y)
+                .map(|y| {
+                    (window.min.x..=window.max.x)
+                        .map(|x| match self.grid[y as usize][x as usize].best_cost {
+                            UNREACHED => f32::INFINITY,
+                            best_cost => best_cost as f32,
+                        })
+                        .collect::<Vec<f32>>()
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for y in rect.min.y as usize..=rect.max.y as usize {
+            for x in rect.min.x as usize..=rect.max.x as usize {
                 let cell = &self.grid[y][x]; // Immutable borrow to get best_cost
                 let mut best_cost = cell.best_cost;
                 let mut best_direction = GridDirection::None;
@@ -66,6 +266,12 @@ impl FlowField {
 
                     if nx >= 0 && nx < grid_size_x as isize && ny >= 0 && ny < grid_size_y as isize
                     {
+                        if direction.is_diagonal()
+                            && self.cuts_corner(IVec2::new(x as i32, y as i32), delta)
+                        {
+                            continue;
+                        }
+
                         let neighbor = &self.grid[ny as usize][nx as usize];
                         if neighbor.best_cost < best_cost {
                             best_cost = neighbor.best_cost;
@@ -74,8 +280,28 @@ impl FlowField {
                     }
                 }
 
+                let cell = &self.grid[y][x];
+                let mut flow = match &costs {
+                    Some(_) if cell.best_cost == UNREACHED => Vec2::ZERO,
+                    Some(costs) => arrival_gradient(costs, cell.idx - window.min),
+                    None => cell.flow,
+                };
+
+                // fall back to the best direction where there is no gradient, like in blocked cells, or where the
+                // gradient leans past a corner the units can't cut
+                if flow == Vec2::ZERO || self.leans_across_corner(cell.idx, flow) {
+                    flow = best_direction.vector().as_vec2().normalize_or_zero();
+                }
+
                 // Now, set the best_direction for the cell
-                self.grid[y][x].best_direction = best_direction;
+                let cell = &mut self.grid[y][x];
+                cell.best_direction = best_direction;
+                cell.flow = flow;
+
+                // nothing is in the way, head straight for the destination
+                if cell.line_of_sight {
+                    cell.flow = (destination_idx - cell.idx).as_vec2().normalize_or_zero();
+                }
             }
         }
     }
@@ -108,19 +334,16 @@ impl FlowField {
         utils::get_cell_from_world_position_helper(pos, size, cell_diameter, &self.grid, offset)
     }
 
-    /// Smoothly sample the best_direction at an arbitrary world-space point
-    /// by bilinearly interpolating between the four enclosing cells.
+    /// Smoothly sample the flow direction at an arbitrary world-space point. See `sample_flow`.
     pub fn sample_direction(&self, world_pos: Vec3, grid: &Grid) -> Vec2 {
-        // 1) Map world -> [0..1] uv over the grid
-        let (u, v) = self.world_to_uv(world_pos, grid);
+        self.sample_flow(world_pos, grid).direction
+    }
 
-        // 2) Scale uv to your discrete grid indices in float-space
-        let cols = self.size.x as f32;
-        let rows = self.size.y as f32;
-        let fx = u * (cols - 1.0);
-        let fy = v * (rows - 1.0);
+    /// Sample the flow at an arbitrary world-space point by bilinearly interpolating the flow of the four
+    /// closest cell centres. Blocked and unreachable cells are left out of the blend.
+    pub fn sample_flow(&self, world_pos: Vec3, grid: &Grid) -> FlowSample {
+        let (fx, fy) = self.world_to_cell_coords(world_pos, grid);
 
-        // 3) Corners
         let x0 = fx.floor() as usize;
         let y0 = fy.floor() as usize;
         let x1 = (x0 + 1).min(self.size.x as usize - 1);
@@ -129,84 +352,696 @@ impl FlowField {
         let sx = fx - x0 as f32;
         let sy = fy - y0 as f32;
 
-        // 4) Pull the four best_direction vectors (Vec2)
-        let d00 = self.grid[y0][x0].best_direction.vector().as_vec2();
-        let d10 = self.grid[y0][x1].best_direction.vector().as_vec2();
-        let d01 = self.grid[y1][x0].best_direction.vector().as_vec2();
-        let d11 = self.grid[y1][x1].best_direction.vector().as_vec2();
+        let corners = [
+            (x0, y0, (1.0 - sx) * (1.0 - sy)),
+            (x1, y0, sx * (1.0 - sy)),
+            (x0, y1, (1.0 - sx) * sy),
+            (x1, y1, sx * sy),
+        ];
+
+        let mut blended = Vec2::ZERO;
+        let mut walkable_weight = 0.0;
+        for (x, y, weight) in corners {
+            let cell = &self.grid[y][x];
+            if cell.cost.is_blocked() || cell.best_cost == UNREACHED {
+                continue;
+            }
+
+            blended += cell.flow * weight;
+            walkable_weight += weight;
+        }
+
+        let cell = &self.grid[fy.round() as usize][fx.round() as usize];
+        let at_obstacle = cell.cost.is_blocked() || cell.best_cost == UNREACHED;
 
-        // 5) Bilinear lerp
-        let lerp = |a: Vec2, b: Vec2, t: f32| a * (1.0 - t) + b * t;
-        let d0 = lerp(d00, d10, sx);
-        let d1 = lerp(d01, d11, sx);
-        let smooth = lerp(d0, d1, sy).normalize_or_zero();
+        // the sample is surrounded by obstacles, so the only way is out of the cell it is in
+        if walkable_weight == 0.0 {
+            return FlowSample {
+                direction: cell.flow,
+                confidence: 0.0,
+                at_obstacle,
+            };
+        }
 
-        smooth.normalize_or_zero()
+        FlowSample {
+            direction: blended.normalize_or_zero(),
+            confidence: blended.length().min(1.0),
+            at_obstacle,
+        }
     }
 
-    /// Convert a world-space position into UV [0..1] over the grid.
-    fn world_to_uv(&self, world_pos: Vec3, grid: &Grid) -> (f32, f32) {
+    // Convert a world-space position into continuous cell coordinates, where the centre of each cell sits on
+    // whole numbers.
+    fn world_to_cell_coords(&self, world_pos: Vec3, grid: &Grid) -> (f32, f32) {
         // Offset so (0,0) is top-left of your grid
         let local = world_pos - self.offset;
         let cell_d = grid.cell_diameter;
         let cols = self.size.x as f32;
         let rows = self.size.y as f32;
 
-        let u = (local.x + (cols * cell_d * 0.5)) / (cols * cell_d);
-        let v = (local.z + (rows * cell_d * 0.5)) / (rows * cell_d);
+        let fx = (local.x + cols * cell_d * 0.5) / cell_d - 0.5;
+        let fy = (local.z + rows * cell_d * 0.5) / cell_d - 0.5;
+
+        (fx.clamp(0.0, cols - 1.0), fy.clamp(0.0, rows - 1.0))
+    }
+
+    /// Builds the integration field and the flowfield towards the goal cells. The cells are taken from the cache
+    /// if a flowfield with the same goals and settings was already built from the current costfield, and cached
+    /// otherwise.
+    pub fn create_fields(&mut self, grid: &Grid, goal_cells: &[IVec2], cache: &mut FlowFieldCache) {
+        if !self.load_cached_fields(grid, goal_cells, cache) {
+            self.create_integration_field(grid, goal_cells);
+            self.create_flowfield();
+            cache.insert(
+                FlowFieldKey::new(self, goal_cells, grid.version),
+                self.grid.clone(),
+            );
+            self.refresh_destination_cell();
+        }
+    }
 
-        (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0))
+    /// Takes the integration field and the flowfield towards the goal cells from the cache, if a flowfield with the
+    /// same goals and settings was already built from the current costfield. Returns `false` if there was none.
+    pub fn load_cached_fields(
+        &mut self,
+        grid: &Grid,
+        goal_cells: &[IVec2],
+        cache: &mut FlowFieldCache,
+    ) -> bool {
+        let key = FlowFieldKey::new(self, goal_cells, grid.version);
+        let Some(cells) = cache.get(&key) else {
+            return false;
+        };
+
+        self.grid = cells.clone();
+        self.set_goal_cells(goal_cells);
+        self.refresh_destination_cell();
+        true
     }
 
-    fn create_integration_field(&mut self, grid: Vec<Vec<Cell>>, destination_idx: IVec2) {
+    /// Updates the integration field and the flowfield after the costs of the cells in `dirty` changed, instead of
+    /// building them from scratch. Only the costs around the changed cells are propagated again, and flowfields that
+    /// can't reach the changed cells or their neighbours aren't integrated again at all. Eikonal fields are built
+    /// from scratch when the change touches them. The cache is only read, as repairs happen while the costs keep
+    /// changing and a repaired field would rarely be asked for again.
+    pub fn repair_fields(&mut self, grid: &Grid, dirty: IRect, cache: &mut FlowFieldCache) {
+        let goal_cells = self.goal_cells.clone();
+        if self.load_cached_fields(grid, &goal_cells, cache) {
+            return;
+        }
+
+        let changed = self.refresh_cost_field(grid, dirty);
+        let touches_change = changed.iter().any(|idx| {
+            GridDirection::all_directions().iter().any(|direction| {
+                let idx = *idx + direction.vector();
+                self.is_in_bounds(idx)
+                    && self.grid[idx.y as usize][idx.x as usize].best_cost != UNREACHED
+            })
+        });
+
+        if touches_change {
+            match self.integration_mode {
+                IntegrationMode::Dijkstra => {
+                    // only the cells next to a changed cost, integration cost or line of sight get a new flow
+                    let mut flow_rect = None;
+                    for idx in changed.iter() {
+                        grow_rect(&mut flow_rect, *idx);
+                    }
+                    if let Some(repaired) = self.repair_dijkstra(&changed) {
+                        flow_rect = flow_rect.map(|rect| rect.union(repaired));
+                    }
+
+                    if let (&[goal], Some(rect)) = (&goal_cells[..], flow_rect) {
+                        // lines of sight only change beyond the closest changed cell
+                        let offset = (rect.min - goal).max(goal - rect.max).max(IVec2::ZERO);
+                        if let Some(sight) = self.mark_line_of_sight(goal, offset.max_element()) {
+                            flow_rect = flow_rect.map(|rect| rect.union(sight));
+                        }
+                    }
+
+                    if let Some(rect) = flow_rect {
+                        self.derive_flow(IRect::from_corners(
+                            (rect.min - IVec2::ONE).max(IVec2::ZERO),
+                            (rect.max + IVec2::ONE).min(self.size - IVec2::ONE),
+                        ));
+                    }
+                }
+                IntegrationMode::Eikonal => {
+                    self.create_integration_field(grid, &goal_cells);
+                    self.create_flowfield();
+                }
+            }
+        }
+
+        self.refresh_destination_cell();
+    }
+
+    // copies the first goal cell into `destination_cell`, once its flow is known
+    fn refresh_destination_cell(&mut self) {
+        if let Some(first_goal) = self.goal_cells.first() {
+            self.destination_cell = self.grid[first_goal.y as usize][first_goal.x as usize];
+        }
+    }
+
+    /// Builds the integration field: the cheapest cost from every cell to the closest of the goal cells. Call
+    /// `create_flowfield` afterwards to derive the flow directions from it.
+    pub fn create_integration_field(&mut self, grid: &Grid, goal_cells: &[IVec2]) {
         // println!("Start Integration Field Create");
 
-        self.grid = grid;
+        self.create_cost_field(grid);
+        self.integrate(goal_cells);
 
-        // Initialize the destination cell in the grid
-        let dest_cell = &mut self.grid[destination_idx.y as usize][destination_idx.x as usize];
-        dest_cell.cost = 0;
-        dest_cell.best_cost = 0;
-        self.destination_cell = dest_cell.clone();
+        // println!("End Integration Field Create");
+    }
 
-        let mut cells_to_check: VecDeque<IVec2> = VecDeque::new();
-        cells_to_check.push_back(destination_idx);
+    fn set_goal_cells(&mut self, goal_cells: &[IVec2]) {
+        self.goal_cells = goal_cells.to_vec();
+        self.goal_set = goal_cells.iter().copied().collect();
+    }
 
-        while let Some(cur_idx) = cells_to_check.pop_front() {
-            let cur_x = cur_idx.x as usize;
-            let cur_y = cur_idx.y as usize;
+    /// Builds the integration field from the cost field the flowfield already has, without the grid. Use it instead
+    /// of `create_integration_field` to build the field away from the main thread, after calling
+    /// `create_cost_field`.
+    pub fn integrate(&mut self, goal_cells: &[IVec2]) {
+        self.set_goal_cells(goal_cells);
 
-            let cur_cell_best_cost = self.grid[cur_y][cur_x].best_cost;
+        let Some(first_goal) = goal_cells.first() else {
+            return;
+        };
 
-            // Iterate over cardinal directions
-            for direction in GridDirection::cardinal_directions() {
+        // Initialize the goal cells in the grid
+        for idx in goal_cells {
+            self.grid[idx.y as usize][idx.x as usize].best_cost = 0;
+        }
+        self.destination_cell = self.grid[first_goal.y as usize][first_goal.x as usize];
+
+        match self.integration_mode {
+            IntegrationMode::Dijkstra => self.integrate_dijkstra(goal_cells),
+            IntegrationMode::Eikonal => self.integrate_eikonal(goal_cells),
+        }
+
+        // a straight line only leads somewhere with a single goal
+        if let [goal] = goal_cells {
+            self.mark_line_of_sight(*goal, 1);
+        }
+    }
+
+    /// Copies the costfield of the grid into the flowfield, with the costs of its movement profile and the cells
+    /// that are too narrow for its units blocked. This is done by `create_integration_field`.
+    pub fn create_cost_field(&mut self, grid: &Grid) {
+        self.grid = grid.cells_for_profile(self.profile.as_ref());
+        self.block_narrow_cells(
+            self.profile.is_none().then_some(&grid.clearance),
+            grid.cell_diameter,
+        );
+    }
+
+    // blocks the cells that are too narrow for the units, for this flowfield only. A profile can block different
+    // cells, so without the clearance of the grid it is computed from the cells of the flowfield
+    fn block_narrow_cells(&mut self, clearance: Option<&Vec<Vec<f32>>>, cell_diameter: f32) {
+        if self.unit_radius <= 0.0 {
+            return;
+        }
+
+        let clearance = match clearance {
+            Some(clearance) => Cow::Borrowed(clearance),
+            None => Cow::Owned(compute_clearance(&self.grid, cell_diameter)),
+        };
+
+        for (row, clearance_row) in self.grid.iter_mut().zip(clearance.iter()) {
+            for (cell, clearance) in row.iter_mut().zip(clearance_row.iter()) {
+                if *clearance < self.unit_radius {
+                    cell.cost = Cost::Blocked;
+                }
+            }
+        }
+    }
+
+    // copies the costs of the cells around the dirty rectangle from the grid the same way as `create_cost_field`, and
+    // returns the cells whose cost or terrain changed
+    fn refresh_cost_field(&mut self, grid: &Grid, dirty: IRect) -> Vec<IVec2> {
+        // whether a cell is too narrow only depends on the blocked cells within the unit radius of it
+        let margin = (self.unit_radius / grid.cell_diameter).ceil() as i32 + 1;
+        let min = (dirty.min - margin).max(IVec2::ZERO);
+        let max = (dirty.max + margin).min(self.size - IVec2::ONE);
+
+        let clearance = match (&self.profile, self.unit_radius > 0.0) {
+            (Some(profile), true) => Cow::Owned(compute_clearance(
+                &grid.cells_for_profile(Some(profile)),
+                grid.cell_diameter,
+            )),
+            _ => Cow::Borrowed(&grid.clearance),
+        };
+
+        let mut changed = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let idx = IVec2::new(x, y);
+                let mut cost = grid.cost_for_profile(idx, self.profile.as_ref());
+                if self.unit_radius > 0.0 && clearance[y as usize][x as usize] < self.unit_radius {
+                    cost = Cost::Blocked;
+                }
+
+                let terrain = grid.grid[y as usize][x as usize].terrain;
+                let cell = &mut self.grid[y as usize][x as usize];
+                if cell.cost != cost || cell.terrain != terrain {
+                    cell.cost = cost;
+                    cell.terrain = terrain;
+                    changed.push(idx);
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Returns the goal cells that can be reached from any of the `from` cells, using the cost field from
+    /// `create_cost_field`. If none of them can be reached, this is the reachable walkable cell closest to any of the
+    /// goals instead. Returns `None` if no walkable cell can be reached at all.
+    pub fn reachable_goals(&self, goals: &[IVec2], from: &[IVec2]) -> Option<Vec<IVec2>> {
+        let reachable = self.reachable_cells(from);
+        reachable_or_closest_goals(self.size, goals, |idx| {
+            reachable[idx.y as usize][idx.x as usize]
+        })
+    }
+
+    // finds the goals the units can reach and the units that can't reach any of them, using the cost field
+    fn find_reachability(
+        &self,
+        goal_cells: &[IVec2],
+        unit_cells: &[(Entity, IVec2)],
+    ) -> Reachability {
+        let cells: Vec<IVec2> = unit_cells.iter().map(|(_, idx)| *idx).collect();
+        let goals = self
+            .reachable_goals(goal_cells, &cells)
+            .unwrap_or_else(|| goal_cells.to_vec());
+
+        let reachable = self.reachable_cells(&goals);
+        let stranded = unit_cells
+            .iter()
+            .filter(|(_, idx)| {
+                !self.can_walk_into(*idx, |idx| reachable[idx.y as usize][idx.x as usize])
+            })
+            .map(|(unit, _)| *unit)
+            .collect();
+
+        Reachability {
+            adjusted: goals != goal_cells,
+            goals,
+            stranded,
+        }
+    }
+
+    // flood fills the walkable cells that can be reached from any of the given cells, moving the same way as the
+    // integration field
+    fn reachable_cells(&self, from: &[IVec2]) -> Vec<Vec<bool>> {
+        let mut reachable = vec![vec![false; self.size.x as usize]; self.size.y as usize];
+        let mut visited = reachable.clone();
+        let mut cells_to_check = VecDeque::new();
+
+        for idx in from {
+            visited[idx.y as usize][idx.x as usize] = true;
+            cells_to_check.push_back(*idx);
+        }
+
+        // the eikonal solver only integrates between cardinal neighbours, so it never passes a diagonal gap
+        let directions = match self.integration_mode {
+            IntegrationMode::Dijkstra => GridDirection::cardinal_and_intercardinal_directions(),
+            IntegrationMode::Eikonal => GridDirection::cardinal_directions(),
+        };
+
+        while let Some(cur_idx) = cells_to_check.pop_front() {
+            // units pushed into a blocked cell can still walk out of it
+            if !self.grid[cur_idx.y as usize][cur_idx.x as usize]
+                .cost
+                .is_blocked()
+            {
+                reachable[cur_idx.y as usize][cur_idx.x as usize] = true;
+            }
+
+            for direction in directions.iter() {
                 let delta = direction.vector();
                 let neighbor_idx = cur_idx + delta;
+                if neighbor_idx.x < 0
+                    || neighbor_idx.x >= self.size.x
+                    || neighbor_idx.y < 0
+                    || neighbor_idx.y >= self.size.y
+                    || visited[neighbor_idx.y as usize][neighbor_idx.x as usize]
+                    || self.grid[neighbor_idx.y as usize][neighbor_idx.x as usize]
+                        .cost
+                        .is_blocked()
+                    || (direction.is_diagonal() && self.cuts_corner(cur_idx, delta))
+                {
+                    continue;
+                }
+
+                visited[neighbor_idx.y as usize][neighbor_idx.x as usize] = true;
+                cells_to_check.push_back(neighbor_idx);
+            }
+        }
+
+        reachable
+    }
+
+    fn integrate_dijkstra(&mut self, goal_cells: &[IVec2]) {
+        let mut cells_to_check = BinaryHeap::new();
+        for idx in goal_cells {
+            cells_to_check.push(Reverse((0, idx.x, idx.y)));
+        }
+
+        self.propagate_dijkstra(cells_to_check);
+    }
 
-                if neighbor_idx.x >= 0
-                    && neighbor_idx.x < self.size.x
-                    && neighbor_idx.y >= 0
-                    && neighbor_idx.y < self.size.y
+    // Repairs the integration field after the costs of the changed cells changed. Cells whose cost no longer comes
+    // from any neighbour are reset first, then the costs are propagated again from the cells around the change.
+    // Returns the rectangle of cells whose integration cost changed.
+    fn repair_dijkstra(&mut self, changed: &[IVec2]) -> Option<IRect> {
+        // a changed cell also changes the cost of its neighbours and the diagonals passing its corners
+        let mut around_change: Vec<IVec2> = Vec::new();
+        let mut is_around_change = vec![vec![false; self.size.x as usize]; self.size.y as usize];
+        for idx in changed {
+            for direction in GridDirection::all_directions() {
+                let neighbor_idx = *idx + direction.vector();
+                if self.is_in_bounds(neighbor_idx)
+                    && !is_around_change[neighbor_idx.y as usize][neighbor_idx.x as usize]
                 {
+                    is_around_change[neighbor_idx.y as usize][neighbor_idx.x as usize] = true;
+                    around_change.push(neighbor_idx);
+                }
+            }
+        }
+
+        // reset every cell that lost the neighbour its cost came from, and check its neighbours in turn
+        let mut repaired = None;
+        let mut reset = Vec::new();
+        let mut cells_to_reset: VecDeque<IVec2> = around_change.iter().copied().collect();
+        while let Some(idx) = cells_to_reset.pop_front() {
+            let best_cost = self.grid[idx.y as usize][idx.x as usize].best_cost;
+            if best_cost == UNREACHED
+                || self.goal_set.contains(&idx)
+                || self.lowest_neighbor_cost(idx) <= best_cost
+            {
+                continue;
+            }
+
+            self.grid[idx.y as usize][idx.x as usize].best_cost = UNREACHED;
+            reset.push(idx);
+            grow_rect(&mut repaired, idx);
+            for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                let neighbor_idx = idx + direction.vector();
+                if self.is_in_bounds(neighbor_idx) {
+                    cells_to_reset.push_back(neighbor_idx);
+                }
+            }
+        }
+
+        // every remaining cost is still reachable, so propagating from the cells that got cheaper fixes the rest
+        let mut cells_to_check = BinaryHeap::new();
+        for idx in around_change.into_iter().chain(reset) {
+            if self.goal_set.contains(&idx) {
+                continue;
+            }
+
+            let lowest_cost = self.lowest_neighbor_cost(idx);
+            let cell = &mut self.grid[idx.y as usize][idx.x as usize];
+            if lowest_cost < cell.best_cost {
+                cell.best_cost = lowest_cost;
+                cells_to_check.push(Reverse((lowest_cost, idx.x, idx.y)));
+                grow_rect(&mut repaired, idx);
+            }
+        }
+
+        match (repaired, self.propagate_dijkstra(cells_to_check)) {
+            (Some(repaired), Some(lowered)) => Some(repaired.union(lowered)),
+            (repaired, lowered) => repaired.or(lowered),
+        }
+    }
+
+    // the cheapest cost of the cell through any of its neighbours, or UNREACHED if it is blocked or none of them
+    // are reached
+    fn lowest_neighbor_cost(&self, idx: IVec2) -> u32 {
+        let Cost::Walkable(cost) = self.grid[idx.y as usize][idx.x as usize].cost else {
+            return UNREACHED;
+        };
+
+        let mut lowest_cost = UNREACHED;
+        for direction in GridDirection::cardinal_and_intercardinal_directions() {
+            let neighbor_idx = idx + direction.vector();
+            if !self.is_in_bounds(neighbor_idx)
+                || (direction.is_diagonal() && self.cuts_corner(neighbor_idx, idx - neighbor_idx))
+            {
+                continue;
+            }
+
+            let neighbor_best_cost =
+                self.grid[neighbor_idx.y as usize][neighbor_idx.x as usize].best_cost;
+            if neighbor_best_cost == UNREACHED {
+                continue;
+            }
+
+            let best_cost = neighbor_best_cost
+                .saturating_add(step_cost(cost, direction))
+                .min(MAX_INTEGRATION_COST);
+            lowest_cost = lowest_cost.min(best_cost);
+        }
+
+        lowest_cost
+    }
+
+    // Dijkstra with lazy deletion: every cell is settled once, stale queue entries are skipped. Returns the rectangle
+    // of cells whose cost was lowered.
+    fn propagate_dijkstra(
+        &mut self,
+        mut cells_to_check: BinaryHeap<Reverse<(u32, i32, i32)>>,
+    ) -> Option<IRect> {
+        let mut lowered = None;
+        while let Some(Reverse((cur_cell_best_cost, cur_x, cur_y))) = cells_to_check.pop() {
+            let cur_idx = IVec2::new(cur_x, cur_y);
+            if cur_cell_best_cost > self.grid[cur_y as usize][cur_x as usize].best_cost {
+                continue;
+            }
+
+            // Iterate over all 8 neighbours, diagonal steps cost √2 times as much
+            for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                let delta = direction.vector();
+                let neighbor_idx = cur_idx + delta;
+
+                if self.is_in_bounds(neighbor_idx) {
+                    if direction.is_diagonal() && self.cuts_corner(cur_idx, delta) {
+                        continue;
+                    }
+
                     let neighbor_x = neighbor_idx.x as usize;
                     let neighbor_y = neighbor_idx.y as usize;
 
                     let neighbor_cell = &mut self.grid[neighbor_y][neighbor_x];
 
-                    if neighbor_cell.cost == u8::MAX {
+                    let Cost::Walkable(cost) = neighbor_cell.cost else {
                         continue;
-                    }
+                    };
 
-                    let tentative_best_cost = neighbor_cell.cost as u16 + cur_cell_best_cost;
+                    let tentative_best_cost = cur_cell_best_cost
+                        .saturating_add(step_cost(cost, direction))
+                        .min(MAX_INTEGRATION_COST);
                     if tentative_best_cost < neighbor_cell.best_cost {
                         neighbor_cell.best_cost = tentative_best_cost;
-                        cells_to_check.push_back(neighbor_idx);
+                        cells_to_check.push(Reverse((
+                            tentative_best_cost,
+                            neighbor_idx.x,
+                            neighbor_idx.y,
+                        )));
+                        grow_rect(&mut lowered, neighbor_idx);
                     }
                 }
             }
         }
 
-        // println!("End Integration Field Create");
+        lowered
+    }
+
+    fn is_in_bounds(&self, idx: IVec2) -> bool {
+        idx.x >= 0 && idx.x < self.size.x && idx.y >= 0 && idx.y < self.size.y
+    }
+
+    // Fast marching: cells are accepted in order of arrival time, and each arrival time solves the Eikonal
+    // equation |∇T| = cost from the accepted neighbours. The flow of each cell follows the arrival time gradient.
+    fn integrate_eikonal(&mut self, goal_cells: &[IVec2]) {
+        let width = self.size.x as usize;
+        let height = self.size.y as usize;
+
+        let mut times = vec![vec![f32::INFINITY; width]; height];
+        let mut accepted = vec![vec![false; width]; height];
+        for idx in goal_cells {
+            times[idx.y as usize][idx.x as usize] = 0.0;
+        }
+
+        // arrival times are never negative, so their bits sort in the same order as the times themselves
+        let mut cells_to_check = BinaryHeap::new();
+        for idx in goal_cells {
+            cells_to_check.push(Reverse((0_u32, idx.x, idx.y)));
+        }
+
+        while let Some(Reverse((_, cur_x, cur_y))) = cells_to_check.pop() {
+            if accepted[cur_y as usize][cur_x as usize] {
+                continue;
+            }
+            accepted[cur_y as usize][cur_x as usize] = true;
+
+            for direction in GridDirection::cardinal_directions() {
+                let neighbor_idx = IVec2::new(cur_x, cur_y) + direction.vector();
+                if neighbor_idx.x < 0
+                    || neighbor_idx.x >= self.size.x
+                    || neighbor_idx.y < 0
+                    || neighbor_idx.y >= self.size.y
+                {
+                    continue;
+                }
+
+                let neighbor_x = neighbor_idx.x as usize;
+                let neighbor_y = neighbor_idx.y as usize;
+                if accepted[neighbor_y][neighbor_x] {
+                    continue;
+                }
+
+                let Cost::Walkable(cost) = self.grid[neighbor_y][neighbor_x].cost else {
+                    continue;
+                };
+
+                let time = solve_eikonal(&times, &accepted, neighbor_idx, cost as f32);
+                if time < times[neighbor_y][neighbor_x] {
+                    times[neighbor_y][neighbor_x] = time;
+                    cells_to_check.push(Reverse((time.to_bits(), neighbor_idx.x, neighbor_idx.y)));
+                }
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let time = times[y][x];
+                if time.is_infinite() {
+                    continue;
+                }
+
+                // float to int casts saturate, so huge times stay below UNREACHED
+                let cell = &mut self.grid[y][x];
+                cell.best_cost = (time.round() as u32).min(MAX_INTEGRATION_COST);
+                cell.flow = arrival_gradient(&times, IVec2::new(x as i32, y as i32));
+            }
+        }
+    }
+
+    // Marks the cells with a straight line to the destination that crosses no blocked cells and no change in cost.
+    // Cells are visited in rings around the destination, so the cells a line passes through on its way to the
+    // destination are always decided first. Rings closer than `first_ring` are left as they are, since their lines
+    // never pass further out. Returns the rectangle of cells whose line of sight changed.
+    fn mark_line_of_sight(&mut self, destination_idx: IVec2, first_ring: i32) -> Option<IRect> {
+        let dest = destination_idx;
+        self.grid[dest.y as usize][dest.x as usize].line_of_sight = true;
+
+        let mut changed = None;
+        let max_ring =
+            (dest.x.max(self.size.x - 1 - dest.x)).max(dest.y.max(self.size.y - 1 - dest.y));
+        for ring in first_ring.max(1)..=max_ring {
+            let min_x = (dest.x - ring).max(0);
+            let max_x = (dest.x + ring).min(self.size.x - 1);
+
+            for y in (dest.y - ring).max(0)..=(dest.y + ring).min(self.size.y - 1) {
+                // the top and bottom rows of a ring are full, the other rows only have their two ends
+                let xs: Vec<i32> = if (y - dest.y).abs() == ring {
+                    (min_x..=max_x).collect()
+                } else {
+                    [dest.x - ring, dest.x + ring]
+                        .into_iter()
+                        .filter(|x| *x >= 0 && *x < self.size.x)
+                        .collect()
+                };
+
+                for x in xs {
+                    let idx = IVec2::new(x, y);
+                    let line_of_sight = self.has_line_of_sight(idx, dest);
+                    let cell = &mut self.grid[y as usize][x as usize];
+                    if cell.line_of_sight != line_of_sight {
+                        cell.line_of_sight = line_of_sight;
+                        grow_rect(&mut changed, idx);
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    // a cell has line of sight if the one or two cells the line crosses one step closer to the destination have it
+    fn has_line_of_sight(&self, idx: IVec2, dest: IVec2) -> bool {
+        let cell = &self.grid[idx.y as usize][idx.x as usize];
+        if cell.cost.is_blocked() || cell.best_cost == UNREACHED {
+            return false;
+        }
+
+        let offset = idx - dest;
+        let (major, minor) = if offset.x.abs() >= offset.y.abs() {
+            (IVec2::X, IVec2::Y)
+        } else {
+            (IVec2::Y, IVec2::X)
+        };
+        let major_len = offset.dot(major);
+        let minor_len = offset.dot(minor);
+
+        // where the line crosses the minor axis one step closer to the destination
+        let steps = major_len.abs();
+        let crossing = minor_len as f32 * (steps - 1) as f32 / steps as f32;
+        let previous = dest + major * (major_len - major_len.signum());
+        let low = previous + minor * crossing.floor() as i32;
+        let high = previous + minor * crossing.ceil() as i32;
+
+        // an exact diagonal passes through a corner, which is only clear if the corner cutting policy allows it
+        if low == high && crossing as i32 != minor_len && self.cuts_corner(idx, low - idx) {
+            return false;
+        }
+
+        [low, high].iter().all(|prev_idx| {
+            let prev = &self.grid[prev_idx.y as usize][prev_idx.x as usize];
+            prev.line_of_sight && (*prev_idx == dest || prev.cost == cell.cost)
+        })
+    }
+
+    // true if a unit in the cell can walk into the reachable area. Units pushed into a blocked cell can still walk
+    // out of it into a reachable neighbour.
+    fn can_walk_into(&self, idx: IVec2, is_reachable: impl Fn(IVec2) -> bool) -> bool {
+        if !self.grid[idx.y as usize][idx.x as usize].cost.is_blocked() {
+            return is_reachable(idx);
+        }
+
+        GridDirection::cardinal_and_intercardinal_directions()
+            .iter()
+            .any(|direction| {
+                let neighbor = idx + direction.vector();
+                neighbor.cmpge(IVec2::ZERO).all()
+                    && neighbor.cmplt(self.size).all()
+                    && is_reachable(neighbor)
+            })
+    }
+
+    // true if the flow leans towards a diagonal neighbour that can't be stepped into, because it is blocked or
+    // unreachable, or because the corner cutting policy forbids the step
+    fn leans_across_corner(&self, idx: IVec2, flow: Vec2) -> bool {
+        if flow.x == 0.0 || flow.y == 0.0 {
+            return false;
+        }
+
+        let delta = IVec2::new(flow.x.signum() as i32, flow.y.signum() as i32);
+        let diagonal = idx + delta;
+        if !self.is_in_bounds(diagonal) {
+            return true;
+        }
+
+        self.grid[diagonal.y as usize][diagonal.x as usize].best_cost == UNREACHED
+            || self.cuts_corner(idx, delta)
+    }
+
+    // true if the corner cutting policy forbids a diagonal step
+    fn cuts_corner(&self, idx: IVec2, delta: IVec2) -> bool {
+        self.corner_cutting.cuts_corner(&self.grid, idx, delta)
     }
 }
 
@@ -238,20 +1073,20 @@ pub fn flowfield_group_stop_system(
             .filter(|&u| q_dest.get(u).is_err())
             .collect();
 
-        // 3) If none yet, pick the first within threshold → remove Destination
-        if !any_arrived {
-            let threshold2 = 25.0;
-            if let Some(&winner) = ff.units.iter().find(|&&u| {
-                q_tf.get(u)
-                    .map(|(tf, _)| {
-                        tf.translation
-                            .distance_squared(ff.destination_cell.world_pos)
-                            < threshold2
-                    })
-                    .unwrap_or(false)
-            }) {
-                cmds.entity(winner).remove::<Destination>();
-                arrived_list.push(winner);
+        // 3) Every unit that entered a goal cell has arrived → remove Destination
+        for &u in &ff.units {
+            if q_dest.get(u).is_err() {
+                continue;
+            }
+            let Ok((tf, _)) = q_tf.get(u) else {
+                continue;
+            };
+
+            // goal cells are the only cells with an integration cost of 0
+            let idx = grid.get_cell_from_world_position(tf.translation).idx;
+            if ff.grid[idx.y as usize][idx.x as usize].best_cost == 0 {
+                cmds.entity(u).remove::<Destination>();
+                arrived_list.push(u);
                 any_arrived = true;
             }
         }
@@ -302,27 +1137,397 @@ pub fn flowfield_group_stop_system(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn initialize_flowfield(
     trigger: Trigger<InitializeFlowFieldEv>,
     mut cmds: Commands,
-    grid: ResMut<Grid>,
+    mut grid: ResMut<Grid>,
+    mut cache: ResMut<FlowFieldCache>,
     mut q_ff: Query<(Entity, &mut FlowField)>,
-    mut _meshes: ResMut<Assets<Mesh>>, // TODO: Remove
-    mut _materials: ResMut<Assets<StandardMaterial>>, // TODO: Remove
-    q_destination_radius: Query<(Entity, &DestinationRadius)>, // TODO: Remove
+    mut q_pending: Query<(Entity, &mut PendingFlowField)>,
+    mut orders: ResMut<FlowFieldOrders>,
+    q_profiles: Query<&MovementProfile>,
+    q_transforms: Query<&Transform>,
 ) {
-    let destination_pos = trigger.event().destination_pos;
+    let destination = trigger.event().destination.clone();
+    let unit_radius = trigger.event().unit_radius;
+    let integration_mode = trigger
+        .event()
+        .integration_mode
+        .unwrap_or(grid.integration_mode);
     let units = trigger.event().entities.clone();
-    if units.is_empty() {
+    let goal_cells = destination.cells(&grid);
+    if units.is_empty() || goal_cells.is_empty() {
         return;
     }
 
-    // insert Destination component to all units
+    // a newer order replaces the pending ones of its units. Dropping the task of an empty one cancels it
+    let order = orders.next;
+    orders.next += 1;
     for unit in units.iter() {
-        cmds.entity(*unit).insert(Destination);
+        orders.latest.insert(*unit, order);
     }
+    for (pending_ent, mut pending) in q_pending.iter_mut() {
+        pending.units.retain(|ent| !units.contains(ent));
+        if pending.units.is_empty() {
+            cmds.entity(pending_ent).despawn();
+        }
+    }
+
+    // cells blocked or unblocked since the last `UpdateCostEv` are labeled before the regions are used below
+    grid.update_regions();
 
-    // Remove existing flowfields that contain any of the units
+    // units with different movement profiles can't share a flowfield
+    let mut groups: Vec<(Option<MovementProfile>, Vec<Entity>)> = Vec::new();
+    for unit in units.iter() {
+        let profile = q_profiles.get(*unit).ok().cloned();
+        match groups.iter_mut().find(|(p, _)| *p == profile) {
+            Some((_, group)) => group.push(*unit),
+            None => groups.push((profile, vec![*unit])),
+        }
+    }
+
+    for (profile, group) in groups {
+        let mut ff = FlowField::new(grid.size, group.clone(), group.len() as f32, Vec3::ZERO);
+        ff.unit_radius = unit_radius;
+        ff.profile = profile;
+        ff.integration_mode = integration_mode;
+        ff.corner_cutting = grid.corner_cutting;
+
+        let unit_cells: Vec<(Entity, IVec2)> = group
+            .iter()
+            .filter_map(|unit| {
+                let tf = q_transforms.get(*unit).ok()?;
+                Some((*unit, grid.get_cell_from_world_position(tf.translation).idx))
+            })
+            .collect();
+
+        // a blocked or unreachable goal would leave the units grinding against a wall, so only head for the goals
+        // they can reach, or the closest cell they can reach if there are none. Units stranded on another island
+        // can't get there at all, so they are left out of the flowfield. The regions of the grid tell right away when
+        // the flowfield moves over the grid's own costs, otherwise the build task finds out
+        let from_regions = ff.profile.is_none()
+            && ff.unit_radius <= 0.0
+            && ff.integration_mode == IntegrationMode::Dijkstra;
+        let goals = if from_regions {
+            let reachability = reachability_from_regions(&grid, &goal_cells, &unit_cells);
+            for unit in reachability.stranded.iter() {
+                orders.latest.remove(unit);
+            }
+            let goals = reachability.goals.clone();
+            report_reachability(
+                &mut cmds,
+                &grid,
+                &mut ff,
+                reachability,
+                &destination,
+                &mut q_ff,
+            );
+            if ff.units.is_empty() {
+                continue;
+            }
+            goals
+        } else {
+            goal_cells.clone()
+        };
+
+        // reuse a field that was already built. Without the regions, it is only reused if every unit can walk into
+        // it, so none of them are stranded
+        if ff.load_cached_fields(&grid, &goals, &mut cache)
+            && (from_regions
+                || unit_cells.iter().all(|(_, idx)| {
+                    ff.can_walk_into(*idx, |idx| {
+                        ff.grid[idx.y as usize][idx.x as usize].best_cost != UNREACHED
+                    })
+                }))
+        {
+            for unit in ff.units.iter() {
+                orders.latest.remove(unit);
+            }
+            spawn_flowfield(&mut cmds, ff, &mut q_ff);
+            continue;
+        }
+
+        // build the fields in the background so the frame doesn't hitch. The task gets its own copy of the costs,
+        // as the grid keeps changing while it runs
+        let cells = grid.cells_for_profile(ff.profile.as_ref());
+        let clearance =
+            (ff.unit_radius > 0.0 && ff.profile.is_none()).then(|| grid.clearance.clone());
+        let cell_diameter = grid.cell_diameter;
+        let units = ff.units.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            ff.grid = cells;
+            ff.block_narrow_cells(clearance.as_ref(), cell_diameter);
+
+            let reachability = (!from_regions).then(|| {
+                let reachability = ff.find_reachability(&goals, &unit_cells);
+                for unit in reachability.stranded.iter() {
+                    ff.remove_unit(*unit);
+                }
+                reachability
+            });
+
+            // the goals are stored in the flowfield by `integrate`
+            let goals = reachability.as_ref().map_or(&goals, |r| &r.goals);
+            if !ff.units.is_empty() {
+                ff.integrate(goals);
+                ff.create_flowfield();
+            }
+            (ff, reachability)
+        });
+
+        cmds.spawn((
+            PendingFlowField {
+                units,
+                order,
+                destination: destination.clone(),
+                version: grid.version,
+                dirty: None,
+                task,
+            },
+            Name::new("PendingFlowField"),
+        ));
+    }
+}
+
+// hands the flowfields that finished building over to their units
+#[allow(clippy::too_many_arguments)]
+fn poll_pending_flowfields(
+    mut cmds: Commands,
+    grid: Res<Grid>,
+    mut cache: ResMut<FlowFieldCache>,
+    mut q_pending: Query<(Entity, &mut PendingFlowField)>,
+    mut q_ff: Query<(Entity, &mut FlowField)>,
+    mut orders: ResMut<FlowFieldOrders>,
+    entities: &Entities,
+) {
+    for (pending_ent, mut pending) in q_pending.iter_mut() {
+        let Some((mut ff, mut reachability)) = check_ready(&mut pending.task) else {
+            continue;
+        };
+        cmds.entity(pending_ent).despawn();
+
+        // units that were despawned or given a newer order in the meantime are left out
+        let stranded = reachability
+            .as_ref()
+            .map(|r| r.stranded.clone())
+            .unwrap_or_default();
+        for unit in ff.units.clone().into_iter().chain(stranded) {
+            let current = orders.latest.get(&unit) == Some(&pending.order);
+            if current || !entities.contains(unit) {
+                orders.latest.remove(&unit);
+            }
+            if !current || !entities.contains(unit) {
+                ff.remove_unit(unit);
+                if let Some(reachability) = reachability.as_mut() {
+                    reachability.stranded.retain(|ent| *ent != unit);
+                }
+            }
+        }
+        if let Some(reachability) = reachability {
+            report_reachability(
+                &mut cmds,
+                &grid,
+                &mut ff,
+                reachability,
+                &pending.destination,
+                &mut q_ff,
+            );
+        }
+        if ff.units.is_empty() {
+            continue;
+        }
+
+        // the costs changed while the fields were being built
+        match pending.dirty {
+            Some(dirty) => ff.repair_fields(&grid, dirty, &mut cache),
+            None => {
+                let key = FlowFieldKey::new(&ff, &ff.goal_cells, pending.version);
+                cache.insert(key, ff.grid.clone());
+                ff.refresh_destination_cell();
+            }
+        }
+
+        spawn_flowfield(&mut cmds, ff, &mut q_ff);
+    }
+}
+
+// leaves the stranded units idling, and tells the others if they head for other goals than the requested ones
+fn report_reachability(
+    cmds: &mut Commands,
+    grid: &Grid,
+    ff: &mut FlowField,
+    reachability: Reachability,
+    destination: &Goal,
+    q_ff: &mut Query<(Entity, &mut FlowField)>,
+) {
+    if !reachability.stranded.is_empty() {
+        for unit in reachability.stranded.iter() {
+            ff.remove_unit(*unit);
+            cmds.entity(*unit).remove::<Destination>();
+        }
+        release_units(cmds, &reachability.stranded, q_ff);
+
+        cmds.trigger(UnreachableDestinationEv {
+            entities: reachability.stranded,
+            destination: destination.clone(),
+        });
+    }
+
+    if reachability.adjusted && !ff.units.is_empty() {
+        cmds.trigger(DestinationAdjustedEv {
+            entities: ff.units.clone(),
+            requested: destination.clone(),
+            goal_positions: reachability
+                .goals
+                .iter()
+                .map(|idx| grid.grid[idx.y as usize][idx.x as usize].world_pos)
+                .collect(),
+        });
+    }
+}
+
+// finds the reachability from the regions of the grid, which match the moves of a flowfield over the grid's own costs
+fn reachability_from_regions(
+    grid: &Grid,
+    goal_cells: &[IVec2],
+    unit_cells: &[(Entity, IVec2)],
+) -> Reachability {
+    let unit_regions: HashSet<u32> = unit_cells
+        .iter()
+        .flat_map(|(_, idx)| regions_around(grid, *idx))
+        .collect();
+    let goals = reachable_or_closest_goals(grid.size, goal_cells, |idx| {
+        grid.regions[idx.y as usize][idx.x as usize]
+            .is_some_and(|region| unit_regions.contains(&region))
+    })
+    .unwrap_or_else(|| goal_cells.to_vec());
+
+    let goal_regions: HashSet<u32> = goals
+        .iter()
+        .filter_map(|idx| grid.regions[idx.y as usize][idx.x as usize])
+        .collect();
+    let stranded = unit_cells
+        .iter()
+        .filter(|(_, idx)| {
+            !regions_around(grid, *idx)
+                .iter()
+                .any(|region| goal_regions.contains(region))
+        })
+        .map(|(unit, _)| *unit)
+        .collect();
+
+    Reachability {
+        adjusted: goals != goal_cells,
+        goals,
+        stranded,
+    }
+}
+
+// the regions a unit in the cell can walk into. Units pushed into a blocked cell can still walk out of it into a
+// neighbour
+fn regions_around(grid: &Grid, idx: IVec2) -> Vec<u32> {
+    if let Some(region) = grid.regions[idx.y as usize][idx.x as usize] {
+        return vec![region];
+    }
+
+    GridDirection::cardinal_and_intercardinal_directions()
+        .iter()
+        .filter_map(|direction| {
+            let neighbor = idx + direction.vector();
+            if neighbor.cmplt(IVec2::ZERO).any() || neighbor.cmpge(grid.size).any() {
+                return None;
+            }
+            grid.regions[neighbor.y as usize][neighbor.x as usize]
+        })
+        .collect()
+}
+
+// the goal cells that are reachable, or the reachable cell closest to any of the goals if there are none. `None` if
+// no cell is reachable at all
+fn reachable_or_closest_goals(
+    size: IVec2,
+    goals: &[IVec2],
+    is_reachable: impl Fn(IVec2) -> bool,
+) -> Option<Vec<IVec2>> {
+    let reachable_goals: Vec<IVec2> = goals
+        .iter()
+        .copied()
+        .filter(|idx| is_reachable(*idx))
+        .collect();
+    if !reachable_goals.is_empty() {
+        return Some(reachable_goals);
+    }
+
+    // search outwards from the goals a ring at a time. Every cell of a ring is at least as far from the goals as
+    // the ring is, so the search stops once the rings are further out than the closest reachable cell found.
+    // Each cell remembers the ring it is in and the closest goal of the cells it was reached from
+    let mut nearest: Vec<Vec<Option<(i32, IVec2)>>> =
+        vec![vec![None; size.x as usize]; size.y as usize];
+    let mut ring: Vec<IVec2> = Vec::new();
+    for goal in goals {
+        if nearest[goal.y as usize][goal.x as usize].is_none() {
+            nearest[goal.y as usize][goal.x as usize] = Some((0, *goal));
+            ring.push(*goal);
+        }
+    }
+
+    let mut closest: Option<(i32, IVec2)> = None;
+    let mut distance = 0;
+    while !ring.is_empty() {
+        if closest.is_some_and(|(closest, _)| closest <= distance * distance) {
+            break;
+        }
+
+        let mut next_ring = Vec::new();
+        for idx in ring {
+            let Some((_, goal)) = nearest[idx.y as usize][idx.x as usize] else {
+                continue;
+            };
+
+            if is_reachable(idx) {
+                let distance = (idx - goal).length_squared();
+                if closest.is_none_or(|(closest, _)| distance < closest) {
+                    closest = Some((distance, idx));
+                }
+            }
+
+            for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                let neighbor = idx + direction.vector();
+                if neighbor.cmplt(IVec2::ZERO).any() || neighbor.cmpge(size).any() {
+                    continue;
+                }
+
+                match &mut nearest[neighbor.y as usize][neighbor.x as usize] {
+                    Some((ring, other_goal)) => {
+                        if *ring == distance + 1
+                            && (neighbor - goal).length_squared()
+                                < (neighbor - *other_goal).length_squared()
+                        {
+                            *other_goal = goal;
+                        }
+                    }
+                    unvisited => {
+                        *unvisited = Some((distance + 1, goal));
+                        next_ring.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        ring = next_ring;
+        distance += 1;
+    }
+
+    closest.map(|(_, idx)| vec![idx])
+}
+
+// takes the units out of the flowfields they are in, and despawns the flowfields that are left without units
+fn release_units(
+    cmds: &mut Commands,
+    units: &[Entity],
+    q_ff: &mut Query<(Entity, &mut FlowField)>,
+) {
     for (ff_ent, mut ff) in q_ff.iter_mut() {
         // 1) Filter out any units from `flowfield.units` that are in `units`
         //    i.e. the ones that are about to be added to the new flowfield.
@@ -333,60 +1538,55 @@ fn initialize_flowfield(
         // 2) If after removal, the flowfield is now empty, *then* despawn it.
         if ff.units.is_empty() {
             cmds.entity(ff_ent).despawn();
-
-            // Also remove any "destination radius" entity that references this flowfield
-            // TODO: Remove
-            for (ent, d) in q_destination_radius.iter() {
-                if d.0 == ff_ent.index() {
-                    cmds.entity(ent).despawn();
-                }
-            }
         }
     }
+}
 
-    // let world_mouse_pos = utils::get_world_pos(map_base, cam.1, cam.0, cursor_pos);
-    let destination_cell = grid.get_cell_from_world_position(destination_pos);
-
-    let mut ff = FlowField::new(grid.size, units.clone(), units.len() as f32, Vec3::ZERO);
-
-    ff.create_integration_field(grid.grid.clone(), destination_cell.idx);
-    ff.create_flowfield();
-    // Spawn the new flowfield
-    // cmds.spawn(flowfield.clone()); // TODO: Uncomment
-    let _ff_ent = cmds
-        .spawn((
-            ff.clone(),
-            Name::new("ParentFlowField"),
-            Transform::default(),
-            GlobalTransform::default(),
-        ))
-        .id();
+// spawns a flowfield whose fields are ready, and moves its units over from their previous flowfields
+fn spawn_flowfield(cmds: &mut Commands, ff: FlowField, q_ff: &mut Query<(Entity, &mut FlowField)>) {
+    release_units(cmds, &ff.units, q_ff);
 
-    // TODO: Remove (debugging purposes)
-    {
-        // let mesh = Mesh3d(_meshes.add(Cylinder::new(ff.destination_radius, 2.0)));
-        // let material = MeshMaterial3d(_materials.add(Color::srgba(1.0, 1.0, 0.33, 0.85)));
-        // cmds.entity(_ff_ent).with_children(|parent| {
-        //     parent.spawn((
-        //         DestinationRadius(_ff_ent.index()),
-        //         mesh,
-        //         material,
-        //         Transform::from_translation(ff.destination_cell.world_pos),
-        //     ));
-        // });
+    // insert Destination component to all units, unless they are despawned before the commands are applied
+    for unit in ff.units.iter() {
+        cmds.entity(*unit).try_insert(Destination);
     }
 
+    cmds.spawn((
+        ff.clone(),
+        Name::new("ParentFlowField"),
+        Transform::default(),
+        GlobalTransform::default(),
+    ));
+
     cmds.trigger(SetActiveFlowfieldEv(Some(ff)));
 }
 
-// TODO: Causes huge performance dip
-// Updates integration fields and flowfields whenever a cost field is updated
+// Repairs the integration fields and flowfields around the cells whose cost changed whenever a cost field is updated
 fn update_fields(
     _trigger: Trigger<UpdateCostEv>,
     mut cmds: Commands,
     mut q_ff: Query<&mut FlowField>,
-    grid: Res<Grid>,
+    mut q_pending: Query<&mut PendingFlowField>,
+    mut grid: ResMut<Grid>,
+    mut cache: ResMut<FlowFieldCache>,
 ) {
+    grid.update_clearance();
+    grid.update_regions();
+    let dirty = grid.take_dirty_rect();
+
+    // flowfields that are still being built are repaired once they are ready
+    if let Some(dirty) = dirty {
+        for mut pending in q_pending.iter_mut() {
+            pending.dirty = Some(match pending.dirty {
+                Some(pending_dirty) => pending_dirty.union(dirty),
+                None => dirty,
+            });
+        }
+    }
+
+    // fields built from the old costfield can never be reused
+    cache.remove_stale(grid.version);
+
     // if there is not FF, then we still want to draw the cost field
     // debug feature only
     if q_ff.is_empty() {
@@ -396,9 +1596,9 @@ fn update_fields(
 
     let mut active_ff = None;
     for mut ff in q_ff.iter_mut() {
-        let dest_idx = ff.destination_cell.idx;
-        ff.create_integration_field(grid.grid.clone(), dest_idx);
-        ff.create_flowfield();
+        if let Some(dirty) = dirty {
+            ff.repair_fields(&grid, dirty, &mut cache);
+        }
 
         active_ff = Some(ff.clone());
     }
@@ -407,3 +1607,80 @@ fn update_fields(
     // debug feature only
     cmds.trigger(SetActiveFlowfieldEv(active_ff));
 }
+
+// grows the rectangle to include the cell
+fn grow_rect(rect: &mut Option<IRect>, idx: IVec2) {
+    *rect = Some(match *rect {
+        Some(rect) => rect.union_point(idx),
+        None => IRect::from_corners(idx, idx),
+    });
+}
+
+// the cost of stepping into a cell with the cost in the direction, diagonal steps cost √2 times as much
+fn step_cost(cost: u16, direction: GridDirection) -> u32 {
+    if direction.is_diagonal() {
+        (cost as f32 * SQRT_2).round() as u32
+    } else {
+        cost as u32
+    }
+}
+
+// the arrival time at a cell from its accepted cardinal neighbours, using the upwind finite difference scheme
+fn solve_eikonal(times: &[Vec<f32>], accepted: &[Vec<bool>], idx: IVec2, cost: f32) -> f32 {
+    let accepted_time = |x: i32, y: i32| {
+        let row = times.get(y as usize)?;
+        let time = row.get(x as usize)?;
+        accepted[y as usize][x as usize].then_some(*time)
+    };
+    let smallest = |a: Option<f32>, b: Option<f32>| match (a, b) {
+        (Some(a), Some(b)) => a.min(b),
+        (Some(t), None) | (None, Some(t)) => t,
+        (None, None) => f32::INFINITY,
+    };
+
+    let horizontal = smallest(
+        accepted_time(idx.x - 1, idx.y),
+        accepted_time(idx.x + 1, idx.y),
+    );
+    let vertical = smallest(
+        accepted_time(idx.x, idx.y - 1),
+        accepted_time(idx.x, idx.y + 1),
+    );
+    let (a, b) = (horizontal.min(vertical), horizontal.max(vertical));
+
+    // the front only reaches the cell from one axis
+    if b - a >= cost {
+        return a + cost;
+    }
+
+    (a + b + (2.0 * cost * cost - (a - b) * (a - b)).sqrt()) / 2.0
+}
+
+// the direction of steepest descent of the arrival times at a cell, normalized
+fn arrival_gradient(times: &[Vec<f32>], idx: IVec2) -> Vec2 {
+    let time = times[idx.y as usize][idx.x as usize];
+    let time_at = |delta: IVec2| {
+        let neighbor = idx + delta;
+        times
+            .get(neighbor.y as usize)
+            .and_then(|row| row.get(neighbor.x as usize))
+            .copied()
+            .unwrap_or(f32::INFINITY)
+    };
+
+    // on each axis, follow the neighbour with the earlier arrival time
+    let axis_descent = |negative: f32, positive: f32| {
+        if negative.min(positive) >= time {
+            0.0
+        } else if negative < positive {
+            negative - time
+        } else {
+            time - positive
+        }
+    };
+
+    let x = axis_descent(time_at(IVec2::NEG_X), time_at(IVec2::X));
+    let y = axis_descent(time_at(IVec2::NEG_Y), time_at(IVec2::Y));
+
+    Vec2::new(x, y).normalize_or_zero()
+}
@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+use crate::components::ObstacleShape;
+
+// the number of edges used to approximate a circle
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Returns the area of a simple polygon using the shoelace formula.
+pub fn polygon_area(polygon: &[Vec2]) -> f32 {
+    if polygon.len() < 3 {
+        return 0.0;
+    }
+
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        area += a.perp_dot(b);
+    }
+
+    area.abs() / 2.0
+}
+
+/// Clips a polygon against an axis aligned rectangle using the Sutherland-Hodgman algorithm.
+///
+/// # Parameters
+///
+/// * `polygon`: The vertices of the polygon, in either winding order.
+/// * `min`: The minimum corner of the rectangle.
+/// * `max`: The maximum corner of the rectangle.
+pub fn clip_to_rect(polygon: &[Vec2], min: Vec2, max: Vec2) -> Vec<Vec2> {
+    // each edge of the rectangle, as (axis, bound, keep values greater than the bound)
+    let edges = [
+        (0, min.x, true),
+        (0, max.x, false),
+        (1, min.y, true),
+        (1, max.y, false),
+    ];
+
+    let mut output = polygon.to_vec();
+    for (axis, bound, keep_greater) in edges {
+        if output.is_empty() {
+            break;
+        }
+
+        let inside = |p: Vec2| {
+            if keep_greater {
+                p[axis] >= bound
+            } else {
+                p[axis] <= bound
+            }
+        };
+
+        let input = std::mem::take(&mut output);
+        for i in 0..input.len() {
+            let cur = input[i];
+            let prev = input[(i + input.len() - 1) % input.len()];
+
+            if inside(cur) {
+                if !inside(prev) {
+                    output.push(intersect(prev, cur, axis, bound));
+                }
+                output.push(cur);
+            } else if inside(prev) {
+                output.push(intersect(prev, cur, axis, bound));
+            }
+        }
+    }
+
+    output
+}
+
+/// Returns the distance from a point to a convex polygon, or 0.0 if the point is inside it.
+pub fn distance_to_polygon(point: Vec2, polygon: &[Vec2]) -> f32 {
+    let mut min_distance = f32::MAX;
+    let mut positive = false;
+    let mut negative = false;
+
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+
+        // which side of the edge the point is on
+        let side = (b - a).perp_dot(point - a);
+        positive |= side > 0.0;
+        negative |= side < 0.0;
+
+        // distance to the closest point on the edge
+        let edge = b - a;
+        let t = ((point - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        min_distance = min_distance.min(point.distance(a + edge * t));
+    }
+
+    // the point is on the same side of every edge, so it is inside
+    if !(positive && negative) {
+        return 0.0;
+    }
+
+    min_distance
+}
+
+// the point where the segment a -> b crosses the given axis aligned line
+fn intersect(a: Vec2, b: Vec2, axis: usize, bound: f32) -> Vec2 {
+    let t = (bound - a[axis]) / (b[axis] - a[axis]);
+    a + (b - a) * t
+}
+
+/// Returns the convex hull of the points in counter-clockwise order, using the monotone chain algorithm.
+pub fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut lower = Vec::with_capacity(points.len());
+    for &point in points.iter() {
+        push_hull_point(&mut lower, point);
+    }
+
+    let mut upper = Vec::with_capacity(points.len());
+    for &point in points.iter().rev() {
+        push_hull_point(&mut upper, point);
+    }
+
+    // the last point of each half is the first point of the other
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+// adds a point to one half of a convex hull, removing any points that would make it concave
+fn push_hull_point(hull: &mut Vec<Vec2>, point: Vec2) {
+    while hull.len() >= 2 {
+        let a = hull[hull.len() - 2];
+        let b = hull[hull.len() - 1];
+        if (b - a).perp_dot(point - a) > 0.0 {
+            break;
+        }
+        hull.pop();
+    }
+    hull.push(point);
+}
+
+/// Converts an obstacle shape into polygons in world space (x and z), using the entity's transform.
+pub fn shape_polygons(shape: &ObstacleShape, transform: &GlobalTransform) -> Vec<Vec<Vec2>> {
+    let mut polygons = Vec::new();
+    local_polygons(shape, Isometry2d::IDENTITY, &mut polygons);
+
+    let affine = transform.affine();
+    for polygon in polygons.iter_mut() {
+        for point in polygon.iter_mut() {
+            *point = affine
+                .transform_point3(Vec3::new(point.x, 0.0, point.y))
+                .xz();
+        }
+    }
+
+    polygons
+}
+
+// converts a shape into polygons relative to the entity, placed with the given isometry
+fn local_polygons(shape: &ObstacleShape, isometry: Isometry2d, polygons: &mut Vec<Vec<Vec2>>) {
+    let polygon = match shape {
+        ObstacleShape::Rectangle(size) => {
+            let half = *size / 2.0;
+            vec![
+                Vec2::new(-half.x, -half.y),
+                Vec2::new(half.x, -half.y),
+                Vec2::new(half.x, half.y),
+                Vec2::new(-half.x, half.y),
+            ]
+        }
+        ObstacleShape::Circle(radius) => arc(Vec2::ZERO, *radius, 0.0, CIRCLE_SEGMENTS),
+        ObstacleShape::Capsule {
+            radius,
+            half_length,
+        } => {
+            let half_segments = CIRCLE_SEGMENTS / 2;
+            let mut points = arc(
+                Vec2::new(0.0, *half_length),
+                *radius,
+                0.0,
+                half_segments + 1,
+            );
+            points.extend(arc(
+                Vec2::new(0.0, -*half_length),
+                *radius,
+                PI,
+                half_segments + 1,
+            ));
+            points
+        }
+        ObstacleShape::ConvexPolygon(points) => points.clone(),
+        ObstacleShape::Compound(shapes) => {
+            for (part_isometry, part) in shapes.iter() {
+                local_polygons(part, isometry * *part_isometry, polygons);
+            }
+            return;
+        }
+    };
+
+    polygons.push(
+        polygon
+            .into_iter()
+            .map(|point| isometry.transform_point(point))
+            .collect(),
+    );
+}
+
+// returns `count` points along a circle, starting at the given angle and spaced as if the circle was
+// made of CIRCLE_SEGMENTS edges. The radius is scaled so the edges enclose the circle.
+fn arc(center: Vec2, radius: f32, start_angle: f32, count: usize) -> Vec<Vec2> {
+    let step = TAU / CIRCLE_SEGMENTS as f32;
+    let radius = radius / (step / 2.0).cos();
+
+    (0..count)
+        .map(|i| center + Vec2::from_angle(start_angle + step * i as f32) * radius)
+        .collect()
+}
@@ -1,15 +1,40 @@
-use bevy::prelude::*;
-use std::collections::HashMap;
+use bevy::{
+    prelude::*,
+    render::{primitives::Aabb, view::VisibilitySystems},
+    transform::TransformSystem,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::SQRT_2;
 
-use crate::{cell::Cell, components::Obstacle, events::UpdateCostEv, utils};
+use crate::{
+    cell::{Cell, CostLayers},
+    components::{
+        CostEffect, CostModifier, MovementProfile, Obstacle, ObstacleFromMesh, ObstacleShape,
+        TerrainType,
+    },
+    cost::Cost,
+    events::UpdateCostEv,
+    footprint,
+    grid_direction::GridDirection,
+    utils,
+};
+
+// the smallest fraction of a cell an obstacle must cover to count as overlapping it
+const MIN_COVERAGE: f32 = 1e-4;
+
+// the smallest multiplier a cost modifier can apply
+const MIN_MULTIPLIER: f32 = 0.01;
 
 pub struct GridPlugin;
 
 impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Grid>().add_systems(
-            Update,
-            (update_costfield_on_add, update_costfield_on_remove),
+            PostUpdate,
+            (resolve_mesh_obstacles, update_costfield)
+                .chain()
+                .after(TransformSystem::TransformPropagate)
+                .after(VisibilitySystems::CalculateBounds),
         );
     }
 }
@@ -24,12 +49,103 @@ pub struct Grid {
     pub cell_radius: f32,
     /// The diameter of each cell in the grid.
     pub cell_diameter: f32,
-    /// 2D vector of cells representing the grid.
+    /// 2D vector of cells representing the grid. Prefer `set_terrain_cost` over editing the cells directly: direct
+    /// edits aren't tracked, so call `mark_all_dirty` after them, and the next `UpdateCostEv` repairs every
+    /// flowfield across the whole grid.
     pub grid: Vec<Vec<Cell>>,
+    /// DONT SET. The terrain, obstacle and modifier layers each cell cost is derived from. Use
+    /// `set_terrain_cost` and `add_cost_modifier` to change them.
+    pub cost_layers: Vec<Vec<CostLayers>>,
     /// The size of the grid in terms of rows and columns. 'x' represents rows, 'y' represents columns.
     pub size: IVec2,
+    /// The fraction (0.0 - 1.0) of a cell an obstacle must cover before the cell is blocked. At 0.0, any
+    /// overlap blocks the cell. Defaults to 0.0.
+    pub coverage_threshold: f32,
     /// DONT SET. A HashMap that stores the occupied cells for each entity.
-    pub occupied_cells: HashMap<u32, Vec<IVec2>>,
+    pub occupied_cells: HashMap<Entity, ObstacleFootprint>,
+    /// DONT SET. A HashMap that stores the affected cells and effect of each `CostModifier` entity.
+    pub modified_cells: HashMap<Entity, ModifierFootprint>,
+    /// DONT SET. The distance from each cell's center to the nearest blocked cell or the edge of the map, in world
+    /// units. Updated whenever the costfield changes.
+    pub clearance: Vec<Vec<f32>>,
+    /// How the integration fields are built. Can be overridden per flowfield with `InitializeFlowFieldEv`.
+    /// Defaults to `IntegrationMode::Dijkstra`.
+    pub integration_mode: IntegrationMode,
+    /// Whether units may move diagonally past the corners of blocked cells. Defaults to `CornerCutting::Forbid`.
+    pub corner_cutting: CornerCutting,
+    /// DONT SET. The connected region each cell belongs to, or `None` for blocked cells. Units can walk between any
+    /// two cells of the same region. Updated whenever the costfield changes.
+    pub regions: Vec<Vec<Option<u32>>>,
+    /// DONT SET. Increased whenever the cost or terrain type of a cell changes. Cached flowfields built for an older
+    /// version are never reused.
+    pub version: u64,
+    /// DONT SET. The smallest rectangle of cells containing every cell whose cost or terrain type changed since the
+    /// flowfields were last updated. The corners are inclusive.
+    pub dirty_rect: Option<IRect>,
+    // the cells that were blocked or unblocked since the regions were last updated
+    region_changes: Vec<IVec2>,
+    // the label of the next region that is flood filled
+    next_region: u32,
+}
+
+/// Whether a diagonal move may pass the corner of a blocked cell. A diagonal move passes between the two cells
+/// that share a side with both the start and end cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum CornerCutting {
+    /// Diagonal moves are only allowed if neither of the two cells is blocked, so units never clip corners.
+    #[default]
+    Forbid,
+    /// Diagonal moves are allowed if at least one of the two cells is free.
+    AllowIfOneFree,
+    /// Diagonal moves are always allowed, even between two blocked cells.
+    Allow,
+}
+
+impl CornerCutting {
+    // true if the policy forbids the diagonal step, based on the two cells it passes between
+    pub(crate) fn cuts_corner(self, cells: &[Vec<Cell>], idx: IVec2, delta: IVec2) -> bool {
+        let horizontal = cells[idx.y as usize][(idx.x + delta.x) as usize]
+            .cost
+            .is_blocked();
+        let vertical = cells[(idx.y + delta.y) as usize][idx.x as usize]
+            .cost
+            .is_blocked();
+
+        match self {
+            CornerCutting::Forbid => horizontal || vertical,
+            CornerCutting::AllowIfOneFree => horizontal && vertical,
+            CornerCutting::Allow => false,
+        }
+    }
+}
+
+/// The algorithm used to build an integration field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum IntegrationMode {
+    /// Shortest paths over the 8 neighbours of each cell. Fast, but the flow follows 8 directions.
+    #[default]
+    Dijkstra,
+    /// Continuous arrival times from a fast marching Eikonal solver. The flow follows the gradient of the arrival
+    /// times, so units move at any angle instead of along 8 directions.
+    Eikonal,
+}
+
+/// The cells an obstacle affects on the costfield.
+#[derive(Clone, Debug, Default, PartialEq, Reflect)]
+pub struct ObstacleFootprint {
+    /// The cells blocked by the obstacle and its padding.
+    pub blocked: Vec<IVec2>,
+    /// The cells in the obstacle's falloff band, and the cost added to each of them.
+    pub falloff: Vec<(IVec2, u16)>,
+}
+
+/// The cells a cost modifier affects on the costfield.
+#[derive(Clone, Debug, PartialEq, Reflect)]
+pub struct ModifierFootprint {
+    /// The cells under the modifier.
+    pub cells: Vec<IVec2>,
+    /// The effect applied to each cell.
+    pub effect: CostEffect,
 }
 
 impl Grid {
@@ -55,8 +171,19 @@ impl Grid {
             cell_diameter,
             cell_radius: cell_diameter / 2.0,
             grid: Vec::default(),
+            cost_layers: vec![vec![CostLayers::default(); size.x as usize]; size.y as usize],
             size,
+            coverage_threshold: 0.0,
             occupied_cells: HashMap::default(),
+            modified_cells: HashMap::default(),
+            clearance: Vec::default(),
+            integration_mode: IntegrationMode::default(),
+            corner_cutting: CornerCutting::default(),
+            regions: Vec::default(),
+            version: 0,
+            dirty_rect: None,
+            region_changes: Vec::default(),
+            next_region: 0,
         };
 
         // Calculate offsets for top-left alignment
@@ -77,6 +204,8 @@ impl Grid {
             })
             .collect::<Vec<_>>();
 
+        grid.update_clearance();
+        grid.relabel_regions();
         grid
     }
 
@@ -100,97 +229,887 @@ impl Grid {
         )
     }
 
+    /// Stamps the footprint of an obstacle onto the costfield, clearing the footprint it previously had.
+    ///
+    /// Returns `true` if the footprint changed.
     pub fn update_cell_costs(
         &mut self,
-        entity_id: u32,
-        obj_transform: &Transform,
-        obj_size: &Obstacle,
+        entity: Entity,
+        obj_transform: &GlobalTransform,
+        obstacle: &Obstacle,
+    ) -> bool {
+        let polygons = footprint::shape_polygons(&obstacle.shape, obj_transform);
+        let mut occupied_cells = ObstacleFootprint {
+            blocked: self.rasterize_polygons(&polygons),
+            falloff: Vec::new(),
+        };
+
+        if obstacle.padding > 0.0 || obstacle.falloff > 0.0 {
+            self.rasterize_padding(&polygons, obstacle, &mut occupied_cells);
+        }
+
+        // the obstacle moved, but still covers the same cells
+        if self.occupied_cells.get(&entity) == Some(&occupied_cells) {
+            return false;
+        }
+
+        // release the old footprint, so the entity's occupancy is only counted once
+        self.release_cells(entity);
+
+        for idx in occupied_cells.blocked.iter() {
+            self.cost_layers[idx.y as usize][idx.x as usize].obstacles += 1;
+            self.refresh_cell_cost(*idx);
+        }
+
+        for (idx, cost) in occupied_cells.falloff.iter() {
+            self.cost_layers[idx.y as usize][idx.x as usize].obstacle_falloff += *cost as u32;
+            self.refresh_cell_cost(*idx);
+        }
+
+        self.occupied_cells.insert(entity, occupied_cells);
+        true
+    }
+
+    // adds the cells around the polygons that fall within the obstacle's padding or falloff band.
+    // Distances are measured from the polygons to each cell's center.
+    fn rasterize_padding(
+        &self,
+        polygons: &[Vec<Vec2>],
+        obstacle: &Obstacle,
+        occupied_cells: &mut ObstacleFootprint,
     ) {
-        let cell_size = self.cell_diameter;
-        let grid_offset_x = -self.size.x as f32 * cell_size / 2.0;
-        let grid_offset_y = -self.size.y as f32 * cell_size / 2.0;
+        let reach = obstacle.padding.max(0.0) + obstacle.falloff.max(0.0);
+        let polygons: Vec<&Vec<Vec2>> = polygons.iter().filter(|p| p.len() >= 3).collect();
+        if polygons.is_empty() {
+            return;
+        }
 
-        let obj_pos = obj_transform.translation;
-        let obj_pos = Vec2::new(obj_pos.x, obj_pos.z);
-        let half_extent = obj_size.0 / 2.0;
+        let points = polygons.iter().flat_map(|polygon| polygon.iter());
+        let aabb_min = points.clone().fold(Vec2::MAX, |acc, p| acc.min(*p)) - reach;
+        let aabb_max = points.fold(Vec2::MIN, |acc, p| acc.max(*p)) + reach;
+        let (min, max) = self.cell_range(aabb_min, aabb_max);
 
-        // Obtain the rotation matrix from the object's rotation.
-        let rotation = Mat3::from_quat(obj_transform.rotation);
+        let covered: HashSet<IVec2> = occupied_cells.blocked.iter().copied().collect();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let idx = IVec2::new(x, y);
+                if covered.contains(&idx) {
+                    continue;
+                }
 
-        // Compute the absolute value of each column of the rotation matrix.
-        // This effectively gives the scaling of the half extents in world space.
-        let abs_rotation = Mat2::from_cols(rotation.x_axis.xy(), rotation.y_axis.xy());
+                let center = self.grid[y as usize][x as usize].world_pos.xz();
+                let distance = polygons
+                    .iter()
+                    .map(|polygon| footprint::distance_to_polygon(center, polygon))
+                    .fold(f32::MAX, f32::min);
 
-        // Compute the world-space half extents by multiplying with the local half extents.
-        let world_half_extent = abs_rotation * half_extent;
+                // with no padding, only the cells covered by the shape itself are blocked
+                if obstacle.padding > 0.0 && distance <= obstacle.padding {
+                    occupied_cells.blocked.push(idx);
+                } else if distance < reach {
+                    // fade the cost out towards the outer edge of the band
+                    let t = 1.0 - (distance - obstacle.padding.max(0.0)) / obstacle.falloff;
+                    let cost = (obstacle.falloff_cost as f32 * t).round() as u16;
+                    if cost > 0 {
+                        occupied_cells.falloff.push((idx, cost));
+                    }
+                }
+            }
+        }
+    }
 
-        // Compute the axis aligned bounding box that encloses the rotated object.
-        let aabb_min = obj_pos - world_half_extent;
-        let aabb_max = obj_pos + world_half_extent;
+    // returns the range of cell indices overlapping the world space (x and z) bounds, clamped to the grid
+    fn cell_range(&self, aabb_min: Vec2, aabb_max: Vec2) -> (IVec2, IVec2) {
+        let grid_offset = -self.size.as_vec2() * self.cell_diameter / 2.0;
+        let min = ((aabb_min - grid_offset) / self.cell_diameter)
+            .floor()
+            .as_ivec2()
+            .max(IVec2::ZERO);
+        let max = ((aabb_max - grid_offset) / self.cell_diameter)
+            .floor()
+            .as_ivec2()
+            .min(self.size - 1);
 
-        // Calculate grid cell indices based on the object's AABB in the xz-plane.
-        let min_x = ((aabb_min.x - grid_offset_x) / cell_size).floor() as isize;
-        let max_x = ((aabb_max.x - grid_offset_x) / cell_size).floor() as isize;
-        let min_y = ((aabb_min.y - grid_offset_y) / cell_size).floor() as isize;
-        let max_y = ((aabb_max.y - grid_offset_y) / cell_size).floor() as isize;
+        (min, max)
+    }
 
+    /// Returns every cell covered by the polygons, given in world space (x and z). A cell only counts
+    /// as covered if the polygons overlap at least `coverage_threshold` of its area. Overlapping
+    /// polygons count their shared area more than once.
+    pub fn rasterize_polygons(&self, polygons: &[Vec<Vec2>]) -> Vec<IVec2> {
         let mut occupied_cells = Vec::new();
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                if x >= 0 && x < self.size.x as isize && y >= 0 && y < self.size.y as isize {
-                    occupied_cells.push(IVec2::new(x as i32, y as i32));
-                    self.grid[y as usize][x as usize].cost = 255;
+        let polygons: Vec<&Vec<Vec2>> = polygons.iter().filter(|p| p.len() >= 3).collect();
+        if polygons.is_empty() {
+            return occupied_cells;
+        }
+
+        let cell_size = self.cell_diameter;
+        let cell_area = cell_size * cell_size;
+        let grid_offset = -self.size.as_vec2() * cell_size / 2.0;
+
+        // Compute the axis aligned bounding box that encloses the polygons.
+        let points = polygons.iter().flat_map(|polygon| polygon.iter());
+        let aabb_min = points.clone().fold(Vec2::MAX, |acc, p| acc.min(*p));
+        let aabb_max = points.fold(Vec2::MIN, |acc, p| acc.max(*p));
+
+        // Calculate grid cell indices based on the AABB, clamped to the grid.
+        let (min, max) = self.cell_range(aabb_min, aabb_max);
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let cell_min = grid_offset + Vec2::new(x as f32, y as f32) * cell_size;
+                let cell_max = cell_min + Vec2::splat(cell_size);
+
+                let covered_area: f32 = polygons
+                    .iter()
+                    .map(|polygon| {
+                        let clipped = footprint::clip_to_rect(polygon, cell_min, cell_max);
+                        footprint::polygon_area(&clipped)
+                    })
+                    .sum();
+                let coverage = covered_area / cell_area;
+
+                // ignore polygons that only touch the cell's edge
+                if coverage > MIN_COVERAGE && coverage + MIN_COVERAGE >= self.coverage_threshold {
+                    occupied_cells.push(IVec2::new(x, y));
                 }
             }
         }
 
-        self.occupied_cells
-            .entry(entity_id)
-            .and_modify(|cells| cells.extend(occupied_cells.iter().cloned()))
-            .or_insert(occupied_cells);
+        occupied_cells
     }
 
-    // TODO: Will eventually need rework. This is setting the cell cost back to 1. What if the cost was originally
-    // something different like rough terrain?
     pub fn reset_cell_costs(&mut self, entities: Vec<Entity>) {
         for ent in entities.iter() {
-            if let Some(occupied_cells) = self.occupied_cells.remove(&ent.index()) {
-                for cell in occupied_cells.iter() {
-                    self.grid[cell.y as usize][cell.x as usize].cost = 1;
+            self.release_cells(*ent);
+        }
+    }
+
+    // removes the entity's claim on its occupied cells. A cell stays blocked until its last occupant leaves.
+    fn release_cells(&mut self, entity: Entity) {
+        let Some(occupied_cells) = self.occupied_cells.remove(&entity) else {
+            return;
+        };
+
+        for cell in occupied_cells.blocked.iter() {
+            let layers = &mut self.cost_layers[cell.y as usize][cell.x as usize];
+            layers.obstacles = layers.obstacles.saturating_sub(1);
+            self.refresh_cell_cost(*cell);
+        }
+
+        for (cell, cost) in occupied_cells.falloff.iter() {
+            let layers = &mut self.cost_layers[cell.y as usize][cell.x as usize];
+            layers.obstacle_falloff = layers.obstacle_falloff.saturating_sub(*cost as u32);
+            self.refresh_cell_cost(*cell);
+        }
+    }
+
+    /// Applies a cost modifier to the cells under its shape, removing the effect it previously had.
+    ///
+    /// Returns `true` if the affected cells or the effect changed.
+    pub fn update_modifier_cells(
+        &mut self,
+        entity: Entity,
+        transform: &GlobalTransform,
+        modifier: &CostModifier,
+    ) -> bool {
+        let polygons = footprint::shape_polygons(&modifier.shape, transform);
+        let footprint = ModifierFootprint {
+            cells: self.rasterize_polygons(&polygons),
+            effect: modifier.effect,
+        };
+
+        if self.modified_cells.get(&entity) == Some(&footprint) {
+            return false;
+        }
+
+        let mut cells = self
+            .modified_cells
+            .remove(&entity)
+            .map(|old| old.cells)
+            .unwrap_or_default();
+        cells.extend(footprint.cells.iter().copied());
+
+        self.modified_cells.insert(entity, footprint);
+        self.refresh_modifier_layers(&cells);
+        true
+    }
+
+    /// Removes the effect of the cost modifiers from the costfield.
+    pub fn reset_modifier_cells(&mut self, entities: Vec<Entity>) {
+        for ent in entities.iter() {
+            self.release_modifier(*ent);
+        }
+    }
+
+    fn release_modifier(&mut self, entity: Entity) {
+        let Some(footprint) = self.modified_cells.remove(&entity) else {
+            return;
+        };
+
+        self.refresh_modifier_layers(&footprint.cells);
+    }
+
+    // recomputes the modifier layers of the cells from the cost modifiers that still cover them, so removing a
+    // modifier restores the layers exactly instead of undoing its effect
+    fn refresh_modifier_layers(&mut self, cells: &[IVec2]) {
+        let mut effects: HashMap<IVec2, (i64, Vec<f32>)> =
+            cells.iter().map(|idx| (*idx, (0, Vec::new()))).collect();
+        for footprint in self.modified_cells.values() {
+            for idx in footprint.cells.iter() {
+                let Some((amount, factors)) = effects.get_mut(idx) else {
+                    continue;
+                };
+
+                match footprint.effect {
+                    CostEffect::Add(add) => *amount += add as i64,
+                    CostEffect::Multiply(factor) => factors.push(factor.max(MIN_MULTIPLIER)),
                 }
             }
         }
+
+        for (idx, (amount, mut factors)) in effects {
+            // the modifiers are stored in no particular order, so multiply in a fixed one to get the same cost
+            factors.sort_by(f32::total_cmp);
+
+            let layers = &mut self.cost_layers[idx.y as usize][idx.x as usize];
+            layers.effect_amount = amount;
+            layers.multiplier = factors.iter().product();
+            self.refresh_cell_cost(idx);
+        }
+    }
+
+    /// Sets the base terrain cost of a cell. Obstacles and modifiers covering the cell are kept,
+    /// so the terrain cost is restored once they are removed.
+    ///
+    /// Trigger an `UpdateCostEv` afterwards to update the flowfields.
+    ///
+    /// # Parameters
+    ///
+    /// * `idx`: The index of the cell.
+    /// * `cost`: The terrain cost. `Cost::Blocked` makes the terrain impassable.
+    pub fn set_terrain_cost(&mut self, idx: IVec2, cost: Cost) {
+        self.cost_layers[idx.y as usize][idx.x as usize].terrain = cost;
+        self.refresh_cell_cost(idx);
+    }
+
+    /// Sets the terrain type of a cell. Units with a `MovementProfile` use it to look up their own cost for the cell.
+    ///
+    /// Trigger an `UpdateCostEv` afterwards to update the flowfields.
+    ///
+    /// # Parameters
+    ///
+    /// * `idx`: The index of the cell.
+    /// * `terrain`: The terrain type.
+    pub fn set_terrain_type(&mut self, idx: IVec2, terrain: TerrainType) {
+        let cell = &mut self.grid[idx.y as usize][idx.x as usize];
+        if cell.terrain != terrain {
+            cell.terrain = terrain;
+            self.mark_dirty(idx);
+        }
+    }
+
+    /// Returns a copy of the cells with their costs derived for a movement profile. Without a profile, this is the
+    /// same as the grid's cells.
+    pub fn cells_for_profile(&self, profile: Option<&MovementProfile>) -> Vec<Vec<Cell>> {
+        let mut cells = self.grid.clone();
+        let Some(profile) = profile else {
+            return cells;
+        };
+
+        for row in cells.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.cost = self.cost_for_profile(cell.idx, Some(profile));
+            }
+        }
+
+        cells
+    }
+
+    /// Returns the cost of a cell derived for a movement profile. Without a profile, this is the cell's own cost.
+    pub fn cost_for_profile(&self, idx: IVec2, profile: Option<&MovementProfile>) -> Cost {
+        let cell = &self.grid[idx.y as usize][idx.x as usize];
+        match profile.and_then(|profile| profile.cost(cell.terrain)) {
+            Some(terrain_cost) => {
+                self.cost_layers[idx.y as usize][idx.x as usize].cost_with_terrain(terrain_cost)
+            }
+            None => cell.cost,
+        }
+    }
+
+    /// Marks every cell as changed. Call this after editing the cells in `grid` directly, then trigger an
+    /// `UpdateCostEv` to update the flowfields.
+    pub fn mark_all_dirty(&mut self) {
+        self.version = self.version.wrapping_add(1);
+        self.dirty_rect = Some(IRect::from_corners(IVec2::ZERO, self.size - IVec2::ONE));
+        self.relabel_regions();
+    }
+
+    /// Returns the rectangle of cells that changed since the last call, and resets it. This is done automatically
+    /// whenever an `UpdateCostEv` is triggered.
+    pub fn take_dirty_rect(&mut self) -> Option<IRect> {
+        self.dirty_rect.take()
+    }
+
+    /// Adds an amount to the modifier layer of a cell. Pass a negative amount to make the cell
+    /// cheaper, or to undo a previous modifier.
+    ///
+    /// Trigger an `UpdateCostEv` afterwards to update the flowfields.
+    ///
+    /// # Parameters
+    ///
+    /// * `idx`: The index of the cell.
+    /// * `amount`: The amount added to the cell cost, in the fixed-point units of `Cost`.
+    pub fn add_cost_modifier(&mut self, idx: IVec2, amount: i32) {
+        let layers = &mut self.cost_layers[idx.y as usize][idx.x as usize];
+        layers.modifier = layers.modifier.saturating_add(amount);
+        self.refresh_cell_cost(idx);
+    }
+
+    /// Recalculates the clearance of every cell. This is done automatically whenever an `UpdateCostEv` is triggered.
+    pub fn update_clearance(&mut self) {
+        self.clearance = compute_clearance(&self.grid, self.cell_diameter);
+    }
+
+    /// Returns the region of the cell at a world position, or `None` if the cell is blocked.
+    pub fn region_of(&self, pos: Vec3) -> Option<u32> {
+        let idx = self.get_cell_from_world_position(pos).idx;
+        self.regions[idx.y as usize][idx.x as usize]
+    }
+
+    /// Returns true if a unit at `a` can walk to `b`. This uses the costs of the grid itself, so movement profiles
+    /// and unit radii are not taken into account.
+    pub fn is_reachable(&self, a: Vec3, b: Vec3) -> bool {
+        match (self.region_of(a), self.region_of(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Relabels the regions around the cells that were blocked or unblocked since the last update. Regions are only
+    /// flood filled again where a blocked cell may have split one, or an unblocked cell merged some. This is done
+    /// automatically whenever an `UpdateCostEv` is triggered.
+    pub fn update_regions(&mut self) {
+        let changes = std::mem::take(&mut self.region_changes);
+        let mut blocked = HashSet::new();
+        let mut unblocked = Vec::new();
+        for idx in changes {
+            let region = &mut self.regions[idx.y as usize][idx.x as usize];
+            if self.grid[idx.y as usize][idx.x as usize].cost.is_blocked() {
+                *region = None;
+                blocked.insert(idx);
+            } else if region.is_none() {
+                // cells blocked and unblocked again since the last update never lost their region
+                unblocked.push(idx);
+            }
+        }
+
+        // touching blocked cells are checked together, as a path through one of them can go on through the others.
+        // All of them are checked before any region is relabeled, so the checks see the old labels.
+        let mut torn: HashMap<u32, Vec<IVec2>> = HashMap::new();
+        let mut checked = HashSet::new();
+        for idx in blocked.iter() {
+            if !checked.insert(*idx) {
+                continue;
+            }
+
+            let mut cluster = vec![*idx];
+            let mut border = HashSet::new();
+            let mut i = 0;
+            while let Some(cur) = cluster.get(i).copied() {
+                i += 1;
+                for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                    let neighbor = cur + direction.vector();
+                    if blocked.contains(&neighbor) {
+                        if checked.insert(neighbor) {
+                            cluster.push(neighbor);
+                        }
+                    } else if self.is_walkable(neighbor)
+                        && self.regions[neighbor.y as usize][neighbor.x as usize].is_some()
+                    {
+                        border.insert(neighbor);
+                    }
+                }
+            }
+
+            for region in self.torn_regions(&border) {
+                torn.entry(region).or_default().extend(
+                    border
+                        .iter()
+                        .filter(|idx| self.regions[idx.y as usize][idx.x as usize] == Some(region)),
+                );
+            }
+        }
+
+        // every piece a torn region fell apart into has border cells, so filling from all of them finds the pieces.
+        // The biggest piece keeps the label, so it is never filled all the way.
+        for (_, seeds) in torn {
+            let (pieces, _) = self.fill_in_turns(&seeds);
+            for piece in pieces {
+                let region = self.new_region();
+                for idx in piece {
+                    self.regions[idx.y as usize][idx.x as usize] = Some(region);
+                }
+            }
+        }
+
+        // unblocked cells join the region around them, and merge the regions they connect
+        for idx in unblocked {
+            if self.regions[idx.y as usize][idx.x as usize].is_some() {
+                continue;
+            }
+
+            // the touching cells that were unblocked as well, and a cell of each region they touch
+            let mut cluster = vec![idx];
+            let mut visited = HashSet::from([idx]);
+            let mut neighbor_regions = HashMap::new();
+            let mut i = 0;
+            while let Some(cur) = cluster.get(i).copied() {
+                i += 1;
+                for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                    let neighbor = cur + direction.vector();
+                    if !self.can_step(cur, direction) || !visited.insert(neighbor) {
+                        continue;
+                    }
+
+                    match self.regions[neighbor.y as usize][neighbor.x as usize] {
+                        Some(region) => {
+                            neighbor_regions.entry(region).or_insert(neighbor);
+                        }
+                        None => cluster.push(neighbor),
+                    }
+                }
+            }
+
+            // the biggest region keeps its label, so it is never filled all the way
+            let seeds: Vec<IVec2> = neighbor_regions.into_values().collect();
+            let (pieces, biggest) = self.fill_in_turns(&seeds);
+            let region = match biggest.or_else(|| pieces.first().map(|piece| piece[0])) {
+                Some(biggest) => self.regions[biggest.y as usize][biggest.x as usize],
+                None => Some(self.new_region()),
+            };
+            for idx in pieces.into_iter().flatten().chain(cluster) {
+                self.regions[idx.y as usize][idx.x as usize] = region;
+            }
+        }
+    }
+
+    /// Labels every region from scratch. Call this after changing `corner_cutting`.
+    pub fn relabel_regions(&mut self) {
+        self.regions = vec![vec![None; self.size.x as usize]; self.size.y as usize];
+        self.region_changes.clear();
+
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let idx = IVec2::new(x, y);
+                if self.is_walkable(idx) && self.regions[y as usize][x as usize].is_none() {
+                    self.flood_region(idx);
+                }
+            }
+        }
+    }
+
+    // the regions that blocking the cells inside the border may have split. Every path that crossed them now has to
+    // go around, which is always possible if the border cells of a region are still connected to each other without
+    // leaving the border or the region.
+    fn torn_regions(&self, border: &HashSet<IVec2>) -> HashSet<u32> {
+        let mut visited = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut torn = HashSet::new();
+
+        for start in border.iter() {
+            let region = self.regions[start.y as usize][start.x as usize];
+            if !visited.insert(*start) {
+                continue;
+            }
+
+            // a region whose border cells fell apart may be split
+            if let Some(region) = region {
+                if !seen.insert(region) {
+                    torn.insert(region);
+                }
+            }
+
+            let mut cells_to_check = vec![*start];
+            while let Some(cur) = cells_to_check.pop() {
+                for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                    let neighbor = cur + direction.vector();
+                    if border.contains(&neighbor)
+                        && self.regions[neighbor.y as usize][neighbor.x as usize] == region
+                        && self.can_step(cur, direction)
+                        && visited.insert(neighbor)
+                    {
+                        cells_to_check.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        torn
+    }
+
+    // fills the regions of the seeds a cell at a time in turns, and joins the fills that meet. Stops once at most one
+    // fill is still going, and returns the cells of every finished fill along with a seed of the unfinished one.
+    // This costs about as much as filling everything but the biggest piece.
+    fn fill_in_turns(&self, seeds: &[IVec2]) -> (Vec<Vec<IVec2>>, Option<IVec2>) {
+        let mut owners: HashMap<IVec2, usize> = HashMap::new();
+        let mut fills: Vec<(IVec2, VecDeque<IVec2>, Vec<IVec2>)> = Vec::new();
+        // the fill each fill was joined into
+        let mut joined: Vec<usize> = Vec::new();
+
+        for seed in seeds {
+            if owners.contains_key(seed) {
+                continue;
+            }
+            owners.insert(*seed, fills.len());
+            joined.push(fills.len());
+            fills.push((*seed, VecDeque::from([*seed]), vec![*seed]));
+        }
+
+        let root = |joined: &[usize], mut fill: usize| {
+            while joined[fill] != fill {
+                fill = joined[fill];
+            }
+            fill
+        };
+
+        loop {
+            let going: Vec<usize> = (0..fills.len())
+                .filter(|fill| joined[*fill] == *fill && !fills[*fill].1.is_empty())
+                .collect();
+            if going.len() <= 1 {
+                let unfinished = going.first().map(|fill| fills[*fill].0);
+                let finished = fills
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(fill, (_, queue, _))| joined[*fill] == *fill && queue.is_empty())
+                    .map(|(_, (_, _, cells))| cells)
+                    .collect();
+                return (finished, unfinished);
+            }
+
+            for fill in going {
+                // the fill may have been joined into another one earlier in this turn
+                let fill = root(&joined, fill);
+                let Some(idx) = fills[fill].1.pop_front() else {
+                    continue;
+                };
+                let region = self.regions[idx.y as usize][idx.x as usize];
+
+                for direction in GridDirection::cardinal_and_intercardinal_directions() {
+                    let neighbor = idx + direction.vector();
+                    if !self.can_step(idx, direction)
+                        || self.regions[neighbor.y as usize][neighbor.x as usize] != region
+                    {
+                        continue;
+                    }
+
+                    match owners.get(&neighbor) {
+                        None => {
+                            owners.insert(neighbor, fill);
+                            fills[fill].1.push_back(neighbor);
+                            fills[fill].2.push(neighbor);
+                        }
+                        Some(owner) => {
+                            let other = root(&joined, *owner);
+                            if other != fill {
+                                joined[other] = fill;
+                                let (_, queue, cells) = std::mem::take(&mut fills[other]);
+                                fills[fill].1.extend(queue);
+                                fills[fill].2.extend(cells);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // gives every cell connected to the seed a new region label
+    fn flood_region(&mut self, seed: IVec2) {
+        let region = self.new_region();
+
+        let directions = GridDirection::cardinal_and_intercardinal_directions();
+        let mut cells_to_check = VecDeque::from([seed]);
+        self.regions[seed.y as usize][seed.x as usize] = Some(region);
+
+        while let Some(idx) = cells_to_check.pop_front() {
+            for direction in directions.iter() {
+                let neighbor = idx + direction.vector();
+                if !self.can_step(idx, *direction)
+                    || self.regions[neighbor.y as usize][neighbor.x as usize] == Some(region)
+                {
+                    continue;
+                }
+
+                self.regions[neighbor.y as usize][neighbor.x as usize] = Some(region);
+                cells_to_check.push_back(neighbor);
+            }
+        }
+    }
+
+    // a label no region has yet
+    fn new_region(&mut self) -> u32 {
+        let region = self.next_region;
+        self.next_region = self.next_region.wrapping_add(1);
+        region
+    }
+
+    // true if a unit in the cell can step into its walkable neighbour in the direction
+    fn can_step(&self, idx: IVec2, direction: GridDirection) -> bool {
+        let delta = direction.vector();
+        self.is_walkable(idx + delta)
+            && !(direction.is_diagonal() && self.corner_cutting.cuts_corner(&self.grid, idx, delta))
+    }
+
+    // grows the dirty rectangle to include the cell, and invalidates the cached flowfields
+    fn mark_dirty(&mut self, idx: IVec2) {
+        self.version = self.version.wrapping_add(1);
+        self.dirty_rect = Some(match self.dirty_rect {
+            Some(rect) => rect.union_point(idx),
+            None => IRect::from_corners(idx, idx),
+        });
+    }
+
+    // true if the index is on the grid and the cell isn't blocked
+    fn is_walkable(&self, idx: IVec2) -> bool {
+        idx.x >= 0
+            && idx.x < self.size.x
+            && idx.y >= 0
+            && idx.y < self.size.y
+            && !self.grid[idx.y as usize][idx.x as usize].cost.is_blocked()
+    }
+
+    // derives the cell cost from its cost layers, and remembers if the cell was blocked or unblocked
+    fn refresh_cell_cost(&mut self, idx: IVec2) {
+        let (x, y) = (idx.x as usize, idx.y as usize);
+        let old_cost = self.grid[y][x].cost;
+        let cost = self.cost_layers[y][x].effective_cost();
+        if cost == old_cost {
+            return;
+        }
+
+        self.grid[y][x].cost = cost;
+        self.mark_dirty(idx);
+
+        if old_cost.is_blocked() != cost.is_blocked() {
+            self.region_changes.push(idx);
+        }
     }
 }
 
-// detects if a new static object has been added and updates the costfield
-fn update_costfield_on_add(
-    mut cmds: Commands,
-    mut grid: ResMut<Grid>,
-    q_objects: Query<(Entity, &Transform, &Obstacle), Added<Obstacle>>,
-) {
-    let objects = q_objects.iter().collect::<Vec<_>>();
-    if objects.is_empty() {
-        return;
+/// Calculates the distance from each cell's center to the nearest blocked cell or the edge of the map, in world
+/// units. Blocked cells have a clearance of 0.0.
+///
+/// Distances are approximated with a two pass chamfer distance transform, using diagonal steps of √2.
+pub fn compute_clearance(cells: &[Vec<Cell>], cell_diameter: f32) -> Vec<Vec<f32>> {
+    let rows = cells.len();
+    let cols = cells.first().map_or(0, |row| row.len());
+
+    // distance in cells to the center of the nearest blocked cell
+    let mut distance: Vec<Vec<f32>> = cells
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| {
+                    if cell.cost.is_blocked() {
+                        0.0
+                    } else {
+                        f32::MAX
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let forward = [
+        (-1, -1, SQRT_2),
+        (0, -1, 1.0),
+        (1, -1, SQRT_2),
+        (-1, 0, 1.0),
+    ];
+    let backward = [(1, 1, SQRT_2), (0, 1, 1.0), (-1, 1, SQRT_2), (1, 0, 1.0)];
+
+    let mut relax = |x: usize, y: usize, passes: &[(i32, i32, f32)]| {
+        for (dx, dy, step) in passes.iter() {
+            let nx = x as i32 + dx;
+            let ny = y as i32 + dy;
+            if nx < 0 || ny < 0 || nx >= cols as i32 || ny >= rows as i32 {
+                continue;
+            }
+
+            let candidate = distance[ny as usize][nx as usize] + step;
+            if candidate < distance[y][x] {
+                distance[y][x] = candidate;
+            }
+        }
+    };
+
+    for y in 0..rows {
+        for x in 0..cols {
+            relax(x, y, &forward);
+        }
     }
 
-    for (ent, transform, size) in objects.iter() {
-        grid.update_cell_costs(ent.index(), transform, size);
+    for y in (0..rows).rev() {
+        for x in (0..cols).rev() {
+            relax(x, y, &backward);
+        }
     }
 
-    cmds.trigger(UpdateCostEv);
+    distance
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(x, &d)| {
+                    if d == 0.0 {
+                        return 0.0;
+                    }
+
+                    // measure to the edge of the blocked cell, not its center
+                    let to_obstacle = (d - 0.5) * cell_diameter;
+                    let to_edge =
+                        (x.min(y).min(cols - 1 - x).min(rows - 1 - y) as f32 + 0.5) * cell_diameter;
+                    to_obstacle.min(to_edge)
+                })
+                .collect()
+        })
+        .collect()
 }
 
-// detects if a static object has been removed and updates the costfield
-fn update_costfield_on_remove(
+// computes the footprint of ObstacleFromMesh entities once all of their meshes have loaded
+fn resolve_mesh_obstacles(
+    mut cmds: Commands,
+    q_pending: Query<(Entity, &GlobalTransform, Option<&Obstacle>), With<ObstacleFromMesh>>,
+    q_children: Query<&Children>,
+    q_meshes: Query<(&GlobalTransform, &Mesh3d, Option<&Aabb>)>,
+    meshes: Res<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
+) {
+    for (ent, obstacle_tf, obstacle) in q_pending.iter() {
+        let world_to_local = obstacle_tf.affine().inverse();
+
+        let mut shapes = Vec::new();
+        let mut loaded = true;
+        let mut failed = 0;
+        for mesh_ent in std::iter::once(ent).chain(q_children.iter_descendants(ent)) {
+            let Ok((mesh_tf, mesh, aabb)) = q_meshes.get(mesh_ent) else {
+                continue;
+            };
+
+            // project the points onto the obstacle's local x and z axes
+            let mesh_to_local = world_to_local * mesh_tf.affine();
+            let points: Vec<Vec2> = match aabb {
+                // the corners of the Aabb
+                Some(aabb) => {
+                    let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+                    (0..8)
+                        .map(|i| {
+                            let sign = Vec3::new(
+                                if i & 1 == 0 { -1.0 } else { 1.0 },
+                                if i & 2 == 0 { -1.0 } else { 1.0 },
+                                if i & 4 == 0 { -1.0 } else { 1.0 },
+                            );
+                            mesh_to_local.transform_point3(center + half * sign).xz()
+                        })
+                        .collect()
+                }
+                // meshes without an Aabb, like ones with NoFrustumCulling, fall back to their vertices
+                None => match meshes.get(&mesh.0) {
+                    Some(mesh) => mesh
+                        .attribute(Mesh::ATTRIBUTE_POSITION)
+                        .and_then(|positions| positions.as_float3())
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|pos| mesh_to_local.transform_point3(Vec3::from(*pos)).xz())
+                        .collect(),
+                    None if asset_server.load_state(&mesh.0).is_failed() => {
+                        failed += 1;
+                        continue;
+                    }
+                    // the mesh asset hasn't loaded yet
+                    None => {
+                        loaded = false;
+                        break;
+                    }
+                },
+            };
+
+            if points.is_empty() {
+                continue;
+            }
+
+            let hull = footprint::convex_hull(points);
+            shapes.push((Isometry2d::IDENTITY, ObstacleShape::ConvexPolygon(hull)));
+        }
+
+        if !loaded {
+            continue;
+        }
+
+        if failed > 0 {
+            warn!("{failed} mesh(es) of ObstacleFromMesh {ent} failed to load and are left out of its footprint");
+        }
+
+        // scenes spawn their meshes a few frames after the root entity. Once a mesh failed, stop waiting for them
+        if shapes.is_empty() {
+            if failed > 0 {
+                cmds.entity(ent).remove::<ObstacleFromMesh>();
+            }
+            continue;
+        }
+
+        let mut obstacle = obstacle.cloned().unwrap_or_default();
+        obstacle.shape = match shapes.len() {
+            1 => shapes.remove(0).1,
+            _ => ObstacleShape::Compound(shapes),
+        };
+
+        cmds.entity(ent)
+            .insert(obstacle)
+            .remove::<ObstacleFromMesh>();
+    }
+}
+
+type ObstacleChanged = Or<(Changed<GlobalTransform>, Changed<Obstacle>)>;
+type ModifierChanged = Or<(Changed<GlobalTransform>, Changed<CostModifier>)>;
+
+// detects obstacles and cost modifiers that have been added, moved, reshaped or removed and updates the costfield.
+// Only a single cost update is triggered per frame, no matter how many of them changed.
+fn update_costfield(
     mut cmds: Commands,
     mut grid: ResMut<Grid>,
+    q_objects: Query<
+        (Entity, &GlobalTransform, &Obstacle),
+        (ObstacleChanged, Without<ObstacleFromMesh>),
+    >,
+    q_modifiers: Query<(Entity, &GlobalTransform, &CostModifier), ModifierChanged>,
     mut removed: RemovedComponents<Obstacle>,
+    mut removed_modifiers: RemovedComponents<CostModifier>,
 ) {
-    let objs: Vec<Entity> = removed.read().collect();
+    let removed_objs: Vec<Entity> = removed.read().collect();
+    let removed_mods: Vec<Entity> = removed_modifiers.read().collect();
+
+    // clear the footprints of removed components first, in case they were re-inserted this frame
+    if !removed_objs.is_empty() {
+        grid.reset_cell_costs(removed_objs);
+    }
+    if !removed_mods.is_empty() {
+        grid.reset_modifier_cells(removed_mods);
+    }
+
+    for (ent, transform, obstacle) in q_objects.iter() {
+        grid.update_cell_costs(ent, transform, obstacle);
+    }
+
+    for (ent, transform, modifier) in q_modifiers.iter() {
+        grid.update_modifier_cells(ent, transform, modifier);
+    }
 
-    if !objs.is_empty() {
-        grid.reset_cell_costs(objs);
+    // footprints that moved without changing any cost leave the flowfields as they are
+    if grid.dirty_rect.is_some() {
         cmds.trigger(UpdateCostEv);
     }
 }
@@ -43,6 +43,12 @@ impl GridDirection {
         }
     }
 
+    /// Returns true for the intercardinal directions (NE, SE, SW, NW)
+    pub fn is_diagonal(self) -> bool {
+        let vector = self.vector();
+        vector.x != 0 && vector.y != 0
+    }
+
     pub fn print_short(&self) {
         match self {
             GridDirection::None => print!("X , "),
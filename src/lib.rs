@@ -7,9 +7,11 @@ use crate::resources::*;
 pub mod boids;
 mod cell;
 pub mod components;
+pub mod cost;
 pub mod debug;
 pub mod events;
 pub mod flowfield;
+mod footprint;
 pub mod grid;
 pub mod grid_direction;
 pub mod resources;
@@ -1,14 +1,290 @@
 use bevy::prelude::*;
 
-use crate::flowfield::FlowField;
+use crate::{
+    cell::Cell,
+    components::MovementProfile,
+    flowfield::FlowField,
+    grid::{CornerCutting, IntegrationMode},
+};
 
 pub struct ResourcesPlugin;
 
 impl Plugin for ResourcesPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<ActiveDbgFlowfield>();
+        app.init_resource::<ActiveDbgFlowfield>()
+            .init_resource::<FlowFieldCache>();
     }
 }
 
 #[derive(Resource, Default, Clone)]
 pub struct ActiveDbgFlowfield(pub Option<FlowField>);
+
+/// Everything a flowfield's integration field and flow directions depend on. Two flowfields with the same key
+/// end up with identical cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowFieldKey {
+    /// The goal cells, sorted so the order they were requested in doesn't matter.
+    pub goal_cells: Vec<IVec2>,
+    pub profile: Option<MovementProfile>,
+    pub unit_radius: f32,
+    pub integration_mode: IntegrationMode,
+    pub corner_cutting: CornerCutting,
+    /// The `Grid::version` the flowfield was built from.
+    pub version: u64,
+}
+
+impl FlowFieldKey {
+    /// Creates the key of a flowfield towards the goal cells, built from a grid at `version`.
+    pub fn new(ff: &FlowField, goal_cells: &[IVec2], version: u64) -> Self {
+        let mut goal_cells = goal_cells.to_vec();
+        goal_cells.sort_by_key(|idx| (idx.y, idx.x));
+
+        FlowFieldKey {
+            goal_cells,
+            profile: ff.profile.clone(),
+            unit_radius: ff.unit_radius,
+            integration_mode: ff.integration_mode,
+            corner_cutting: ff.corner_cutting,
+            version,
+        }
+    }
+}
+
+struct CachedFlowField {
+    key: FlowFieldKey,
+    cells: Vec<Vec<Cell>>,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Reuses the cells of flowfields that were already built, so repeated orders to the same goal don't rebuild
+/// the whole integration field. The least recently used fields are evicted once there are more than
+/// `max_entries` of them, or once they take up more than `max_bytes`.
+///
+/// Fields built from an older `Grid::version` are never reused, and are dropped whenever the costfield changes.
+///
+/// # Example
+/// ```
+/// // keep up to 32 fields, in at most 128 MiB
+/// app.insert_resource(FlowFieldCache::new(32, 128 * 1024 * 1024));
+/// ```
+#[derive(Resource)]
+pub struct FlowFieldCache {
+    /// The most fields kept at once. 0 disables the cache.
+    pub max_entries: usize,
+    /// The most memory the cached cells may take up, in bytes.
+    pub max_bytes: usize,
+    entries: Vec<CachedFlowField>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl Default for FlowFieldCache {
+    fn default() -> Self {
+        FlowFieldCache::new(16, 64 * 1024 * 1024)
+    }
+}
+
+impl FlowFieldCache {
+    pub fn new(max_entries: usize, max_bytes: usize) -> Self {
+        FlowFieldCache {
+            max_entries,
+            max_bytes,
+            entries: Vec::new(),
+            bytes: 0,
+            tick: 0,
+        }
+    }
+
+    /// Returns the cells of the flowfield with the key, and marks it as recently used.
+    pub fn get(&mut self, key: &FlowFieldKey) -> Option<&Vec<Vec<Cell>>> {
+        self.tick += 1;
+        let entry = self.entries.iter_mut().find(|entry| entry.key == *key)?;
+        entry.last_used = self.tick;
+        Some(&entry.cells)
+    }
+
+    /// Stores the cells of a flowfield, evicting the least recently used fields if the cache is full. Fields that
+    /// don't fit in `max_bytes` on their own are not stored.
+    pub fn insert(&mut self, key: FlowFieldKey, cells: Vec<Vec<Cell>>) {
+        let bytes = cells
+            .iter()
+            .map(|row| row.len() * size_of::<Cell>())
+            .sum::<usize>();
+        if self.max_entries == 0 || bytes > self.max_bytes {
+            return;
+        }
+
+        if let Some(i) = self.entries.iter().position(|entry| entry.key == key) {
+            self.remove(i);
+        }
+
+        while self.entries.len() >= self.max_entries || self.bytes + bytes > self.max_bytes {
+            let Some(lru) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            self.remove(lru);
+        }
+
+        self.tick += 1;
+        self.bytes += bytes;
+        self.entries.push(CachedFlowField {
+            key,
+            cells,
+            bytes,
+            last_used: self.tick,
+        });
+    }
+
+    /// Drops every field that wasn't built from the grid at `version`. This is done automatically whenever an
+    /// `UpdateCostEv` is triggered.
+    pub fn remove_stale(&mut self, version: u64) {
+        self.entries.retain(|entry| entry.key.version == version);
+        self.bytes = self.entries.iter().map(|entry| entry.bytes).sum();
+    }
+
+    /// Drops every field.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// The number of cached fields.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The memory taken up by the cached cells, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes
+    }
+
+    fn remove(&mut self, i: usize) {
+        let entry = self.entries.swap_remove(i);
+        self.bytes -= entry.bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::TerrainType, cost::Cost};
+
+    // the key of a default flowfield towards the cell, built from the grid at `version`
+    fn key(goal: IVec2, version: u64) -> FlowFieldKey {
+        FlowFieldKey::new(&FlowField::default(), &[goal], version)
+    }
+
+    fn cells(count: usize) -> Vec<Vec<Cell>> {
+        vec![vec![Cell::default(); count]]
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_field() {
+        let mut cache = FlowFieldCache::new(2, usize::MAX);
+        cache.insert(key(IVec2::ZERO, 0), cells(1));
+        cache.insert(key(IVec2::X, 0), cells(1));
+
+        // using the first field leaves the second one as the least recently used
+        assert!(cache.get(&key(IVec2::ZERO, 0)).is_some());
+        cache.insert(key(IVec2::Y, 0), cells(1));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key(IVec2::X, 0)).is_none());
+        assert!(cache.get(&key(IVec2::ZERO, 0)).is_some());
+        assert!(cache.get(&key(IVec2::Y, 0)).is_some());
+    }
+
+    #[test]
+    fn stays_within_max_bytes() {
+        let field_bytes = 10 * size_of::<Cell>();
+        let mut cache = FlowFieldCache::new(16, field_bytes * 2);
+        for x in 0..4 {
+            cache.insert(key(IVec2::new(x, 0), 0), cells(10));
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.memory_usage(), field_bytes * 2);
+        assert!(cache.get(&key(IVec2::new(2, 0), 0)).is_some());
+        assert!(cache.get(&key(IVec2::new(3, 0), 0)).is_some());
+
+        // a field larger than the whole budget isn't stored, and doesn't evict anything
+        cache.insert(key(IVec2::Y, 0), cells(30));
+        assert!(cache.get(&key(IVec2::Y, 0)).is_none());
+        assert_eq!(cache.len(), 2);
+
+        // replacing a field doesn't count it twice
+        cache.insert(key(IVec2::new(3, 0), 0), cells(10));
+        assert_eq!(cache.memory_usage(), field_bytes * 2);
+    }
+
+    #[test]
+    fn no_entries_disables_the_cache() {
+        let mut cache = FlowFieldCache::new(0, usize::MAX);
+        cache.insert(key(IVec2::ZERO, 0), cells(1));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn remove_stale_drops_fields_from_older_versions() {
+        let mut cache = FlowFieldCache::new(16, usize::MAX);
+        cache.insert(key(IVec2::ZERO, 1), cells(10));
+        cache.insert(key(IVec2::X, 1), cells(10));
+        cache.insert(key(IVec2::Y, 2), cells(10));
+
+        cache.remove_stale(2);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.memory_usage(), 10 * size_of::<Cell>());
+        assert!(cache.get(&key(IVec2::ZERO, 1)).is_none());
+        assert!(cache.get(&key(IVec2::Y, 2)).is_some());
+    }
+
+    #[test]
+    fn keys_depend_on_every_setting() {
+        let ff = FlowField::default();
+        let goals = [IVec2::X, IVec2::Y];
+        let base = FlowFieldKey::new(&ff, &goals, 0);
+
+        // the order of the goals doesn't matter
+        assert_eq!(base, FlowFieldKey::new(&ff, &[IVec2::Y, IVec2::X], 0));
+
+        assert_ne!(base, FlowFieldKey::new(&ff, &goals, 1));
+        assert_ne!(base, FlowFieldKey::new(&ff, &[IVec2::X], 0));
+
+        let changed = [
+            FlowField {
+                profile: Some(MovementProfile::new([(TerrainType::GROUND, Cost::Blocked)])),
+                ..default()
+            },
+            FlowField {
+                unit_radius: 5.0,
+                ..default()
+            },
+            FlowField {
+                integration_mode: IntegrationMode::Eikonal,
+                ..default()
+            },
+            FlowField {
+                corner_cutting: CornerCutting::Allow,
+                ..default()
+            },
+        ];
+
+        let mut cache = FlowFieldCache::default();
+        cache.insert(base, cells(1));
+        for ff in changed {
+            assert!(cache.get(&FlowFieldKey::new(&ff, &goals, 0)).is_none());
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    components::Destination,
+    cost::Cost,
+    events::{InitializeFlowFieldEv, UnreachableDestinationEv},
+    flowfield::{FlowField, FlowfieldPlugin, Goal, PendingFlowField},
+    grid::{Grid, GridPlugin},
+    resources::ResourcesPlugin,
+};
+
+fn app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), TransformPlugin))
+        .init_asset::<Mesh>()
+        .add_plugins((GridPlugin, FlowfieldPlugin, ResourcesPlugin))
+        .insert_resource(Grid::new(10.0, IVec2::new(20, 20), 10.0));
+    app.update();
+    app
+}
+
+fn order(app: &mut App, units: Vec<Entity>) {
+    order_with_radius(app, units, 0.0);
+}
+
+fn order_with_radius(app: &mut App, units: Vec<Entity>, unit_radius: f32) {
+    app.world_mut().trigger(InitializeFlowFieldEv {
+        entities: units,
+        destination: Goal::Position(Vec3::new(85.0, 0.0, 85.0)),
+        unit_radius,
+        ..default()
+    });
+}
+
+// runs the app until every pending flowfield is built and handed over
+fn finish_builds(app: &mut App) {
+    for _ in 0..1000 {
+        app.update();
+        let pending = app
+            .world_mut()
+            .query::<&PendingFlowField>()
+            .iter(app.world())
+            .count();
+        if pending == 0 {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    panic!("the flowfields were never built");
+}
+
+fn flowfields(app: &mut App) -> Vec<FlowField> {
+    app.world_mut()
+        .query::<&FlowField>()
+        .iter(app.world())
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn units_despawned_during_the_build_are_left_out() {
+    let mut app = app();
+    let despawned = app
+        .world_mut()
+        .spawn(Transform::from_xyz(-95.0, 0.0, -95.0))
+        .id();
+    let unit = app
+        .world_mut()
+        .spawn(Transform::from_xyz(-85.0, 0.0, -95.0))
+        .id();
+
+    order(&mut app, vec![despawned, unit]);
+    app.world_mut().despawn(despawned);
+    finish_builds(&mut app);
+
+    let flowfields = flowfields(&mut app);
+    assert_eq!(flowfields.len(), 1);
+    assert_eq!(flowfields[0].units, vec![unit]);
+    assert!(app.world().entity(unit).contains::<Destination>());
+}
+
+#[test]
+fn no_flowfield_is_spawned_once_every_unit_is_despawned() {
+    let mut app = app();
+    let units: Vec<Entity> = (0..3)
+        .map(|i| {
+            let position = Vec3::new(-95.0 + i as f32 * 10.0, 0.0, -95.0);
+            app.world_mut()
+                .spawn(Transform::from_translation(position))
+                .id()
+        })
+        .collect();
+
+    order(&mut app, units.clone());
+    for unit in units {
+        app.world_mut().despawn(unit);
+    }
+    finish_builds(&mut app);
+
+    assert!(flowfields(&mut app).is_empty());
+}
+
+#[derive(Resource, Default)]
+struct Stranded(Vec<Entity>);
+
+#[test]
+fn units_walled_off_from_the_destination_are_left_out() {
+    // the regions tell right away without a unit radius, the build task finds out with one
+    for unit_radius in [0.0, 1.0] {
+        let mut app = app();
+        app.init_resource::<Stranded>().add_observer(
+            |trigger: Trigger<UnreachableDestinationEv>, mut stranded: ResMut<Stranded>| {
+                stranded.0.extend(trigger.event().entities.iter().copied());
+            },
+        );
+
+        let mut grid = app.world_mut().resource_mut::<Grid>();
+        for y in 0..20 {
+            grid.set_terrain_cost(IVec2::new(10, y), Cost::Blocked);
+        }
+        grid.update_clearance();
+
+        let walled_off = app
+            .world_mut()
+            .spawn(Transform::from_xyz(-95.0, 0.0, -95.0))
+            .id();
+        let unit = app
+            .world_mut()
+            .spawn(Transform::from_xyz(95.0, 0.0, -95.0))
+            .id();
+
+        order_with_radius(&mut app, vec![walled_off, unit], unit_radius);
+        finish_builds(&mut app);
+
+        assert_eq!(app.world().resource::<Stranded>().0, vec![walled_off]);
+        let flowfields = flowfields(&mut app);
+        assert_eq!(flowfields.len(), 1);
+        assert_eq!(flowfields[0].units, vec![unit]);
+        assert!(!app.world().entity(walled_off).contains::<Destination>());
+    }
+}
@@ -0,0 +1,134 @@
+// helpers shared by the tests and the integration benchmark
+#![allow(dead_code)]
+
+use std::{collections::VecDeque, f32::consts::SQRT_2};
+
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    cost::Cost,
+    flowfield::{MAX_INTEGRATION_COST, UNREACHED},
+    grid::Grid,
+};
+
+// a xorshift generator, so every run makes the same grids and edits
+pub struct Rng(pub u32);
+
+impl Rng {
+    pub fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    pub fn below(&mut self, max: u32) -> u32 {
+        self.next() % max
+    }
+}
+
+// a grid with pseudo-random terrain costs between 0.5 and 4.0, scattered blocked cells and walls with gaps,
+// the same for every seed
+pub fn weighted_grid(size: IVec2, seed: u32) -> Grid {
+    let mut grid = Grid::new(1.0, size, 10.0);
+    let mut rng = Rng(seed);
+
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let cost = match rng.below(10) {
+                0 => Cost::Blocked,
+                _ => Cost::from_f32(0.5 + rng.below(1000) as f32 / 1000.0 * 3.5),
+            };
+            grid.set_terrain_cost(IVec2::new(x, y), cost);
+        }
+    }
+
+    // horizontal walls with a gap at alternating ends
+    let gap = (size.x / 10).max(2);
+    for (i, y) in (size.y / 4..size.y)
+        .step_by((size.y / 4) as usize)
+        .enumerate()
+    {
+        for x in 0..size.x - gap {
+            let x = match i % 2 {
+                0 => x,
+                _ => x + gap,
+            };
+            grid.set_terrain_cost(IVec2::new(x, y), Cost::Blocked);
+        }
+    }
+
+    grid.update_clearance();
+    grid
+}
+
+// the terrain cost of every cell, None if it is blocked
+pub fn costs(grid: &Grid) -> Vec<Vec<Option<u16>>> {
+    grid.grid
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.cost.value()).collect())
+        .collect()
+}
+
+// the integration field as it was built before the priority queue: a FIFO queue that re-enqueues a
+// cell every time its cost improves, which converges to the same costs as Dijkstra, only slower.
+// Returns the field and the number of cells expanded.
+pub fn fifo_integration_field(
+    costs: &[Vec<Option<u16>>],
+    goals: &[IVec2],
+) -> (Vec<Vec<u32>>, usize) {
+    let size = IVec2::new(costs[0].len() as i32, costs.len() as i32);
+    let mut field = vec![vec![UNREACHED; size.x as usize]; size.y as usize];
+    for goal in goals {
+        field[goal.y as usize][goal.x as usize] = 0;
+    }
+
+    let mut visits = 0;
+    let mut queue: VecDeque<IVec2> = goals.iter().copied().collect();
+    while let Some(cur) = queue.pop_front() {
+        visits += 1;
+        let cur_best_cost = field[cur.y as usize][cur.x as usize];
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let next = cur + IVec2::new(dx, dy);
+                if (dx == 0 && dy == 0)
+                    || next.x < 0
+                    || next.x >= size.x
+                    || next.y < 0
+                    || next.y >= size.y
+                {
+                    continue;
+                }
+
+                // never cut blocked corners
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && (costs[cur.y as usize][next.x as usize].is_none()
+                        || costs[next.y as usize][cur.x as usize].is_none())
+                {
+                    continue;
+                }
+
+                let Some(cost) = costs[next.y as usize][next.x as usize] else {
+                    continue;
+                };
+
+                let step_cost = if diagonal {
+                    (cost as f32 * SQRT_2).round() as u32
+                } else {
+                    cost as u32
+                };
+
+                let best_cost = cur_best_cost
+                    .saturating_add(step_cost)
+                    .min(MAX_INTEGRATION_COST);
+                if best_cost < field[next.y as usize][next.x as usize] {
+                    field[next.y as usize][next.x as usize] = best_cost;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    (field, visits)
+}
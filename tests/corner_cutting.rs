@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    cost::Cost,
+    flowfield::{FlowField, UNREACHED},
+    grid::{CornerCutting, Grid, IntegrationMode},
+    grid_direction::GridDirection,
+};
+
+// builds a grid with the given cells blocked, and a flowfield towards the destination using the policy
+fn flowfield(
+    size: IVec2,
+    blocked: &[IVec2],
+    destination: IVec2,
+    policy: CornerCutting,
+) -> FlowField {
+    let mut grid = Grid::new(1.0, size, 10.0);
+    grid.corner_cutting = policy;
+    for idx in blocked {
+        grid.set_terrain_cost(*idx, Cost::Blocked);
+    }
+
+    let mut ff = FlowField::new(grid.size, Vec::new(), 0.0, Vec3::ZERO);
+    ff.corner_cutting = grid.corner_cutting;
+    ff.create_integration_field(&grid, &[destination]);
+    ff.create_flowfield();
+    ff
+}
+
+fn cell(ff: &FlowField, idx: IVec2) -> (u32, GridDirection) {
+    let cell = &ff.grid[idx.y as usize][idx.x as usize];
+    (cell.best_cost, cell.best_direction)
+}
+
+// checks the flow of the cell, and the flow sampled at its centre
+fn assert_flow(ff: &FlowField, idx: IVec2, expected: Vec2) {
+    let cell = &ff.grid[idx.y as usize][idx.x as usize];
+    assert!(
+        cell.flow.abs_diff_eq(expected, 1e-5),
+        "flow {} at {idx}",
+        cell.flow
+    );
+
+    let grid = Grid::new(1.0, ff.size, 10.0);
+    let sample = ff.sample_flow(cell.world_pos, &grid);
+    assert!(
+        sample.direction.abs_diff_eq(expected, 1e-5),
+        "sampled flow {} at {idx}",
+        sample.direction
+    );
+}
+
+// . # .
+// # x .
+// . . .
+// the destination in the top left corner can only be reached by squeezing between the two blocked cells
+const DIAGONAL_GAP: [IVec2; 2] = [IVec2::new(1, 0), IVec2::new(0, 1)];
+
+// . # .
+// . x .
+// . . .
+// the diagonal from x to the destination in the top left corner passes one blocked cell
+const ONE_SIDE_BLOCKED: [IVec2; 1] = [IVec2::new(1, 0)];
+
+#[test]
+fn forbid_never_cuts_corners() {
+    let ff = flowfield(
+        IVec2::splat(3),
+        &DIAGONAL_GAP,
+        IVec2::ZERO,
+        CornerCutting::Forbid,
+    );
+    assert_eq!(cell(&ff, IVec2::ONE), (UNREACHED, GridDirection::None));
+
+    let ff = flowfield(
+        IVec2::splat(3),
+        &ONE_SIDE_BLOCKED,
+        IVec2::ZERO,
+        CornerCutting::Forbid,
+    );
+    assert_eq!(cell(&ff, IVec2::ONE), (20, GridDirection::West));
+}
+
+#[test]
+fn allow_if_one_free_cuts_corners_with_one_free_side() {
+    let policy = CornerCutting::AllowIfOneFree;
+
+    let ff = flowfield(IVec2::splat(3), &DIAGONAL_GAP, IVec2::ZERO, policy);
+    assert_eq!(cell(&ff, IVec2::ONE), (UNREACHED, GridDirection::None));
+
+    let ff = flowfield(IVec2::splat(3), &ONE_SIDE_BLOCKED, IVec2::ZERO, policy);
+    assert_eq!(cell(&ff, IVec2::ONE), (14, GridDirection::NorthWest));
+}
+
+#[test]
+fn allow_cuts_every_corner() {
+    let ff = flowfield(
+        IVec2::splat(3),
+        &DIAGONAL_GAP,
+        IVec2::ZERO,
+        CornerCutting::Allow,
+    );
+    assert_eq!(cell(&ff, IVec2::ONE), (14, GridDirection::NorthWest));
+
+    let ff = flowfield(
+        IVec2::splat(3),
+        &ONE_SIDE_BLOCKED,
+        IVec2::ZERO,
+        CornerCutting::Allow,
+    );
+    assert_eq!(cell(&ff, IVec2::ONE), (14, GridDirection::NorthWest));
+}
+
+#[test]
+fn flow_pass_respects_the_policy() {
+    // . # . .
+    // . x . .
+    // . . . .
+    // under Forbid, x goes around the corner even though the destination is its lowest cost neighbour
+    let ff = flowfield(
+        IVec2::new(4, 3),
+        &ONE_SIDE_BLOCKED,
+        IVec2::ZERO,
+        CornerCutting::Forbid,
+    );
+    let (_, direction) = cell(&ff, IVec2::ONE);
+    assert_eq!(direction, GridDirection::West);
+
+    // the cell to the right of the blocked one must not cut past it either
+    let (best_cost, direction) = cell(&ff, IVec2::new(2, 0));
+    assert_eq!(best_cost, 40);
+    assert_eq!(direction, GridDirection::South);
+
+    // neither must the continuous flow, nor the flow sampled from it
+    assert_flow(&ff, IVec2::ONE, Vec2::NEG_X);
+    assert_flow(&ff, IVec2::new(2, 0), Vec2::Y);
+
+    // under AllowIfOneFree, x may squeeze past the corner
+    let ff = flowfield(
+        IVec2::new(4, 3),
+        &ONE_SIDE_BLOCKED,
+        IVec2::ZERO,
+        CornerCutting::AllowIfOneFree,
+    );
+    let (_, direction) = cell(&ff, IVec2::ONE);
+    assert_eq!(direction, GridDirection::NorthWest);
+    assert_flow(&ff, IVec2::ONE, Vec2::NEG_ONE.normalize());
+
+    // but nothing flows into the blocked cell
+    assert_flow(&ff, IVec2::new(2, 0), Vec2::Y);
+}
+
+#[test]
+fn flow_doesnt_lean_past_a_blocked_diagonal() {
+    // x . . . .
+    // . # . . .
+    // . . o . .
+    // . . . . .
+    // . . . . .
+    // the arrival times fall off diagonally from o, but the blocked cell is in the way
+    let ff = flowfield(
+        IVec2::splat(5),
+        &[IVec2::ONE],
+        IVec2::ZERO,
+        CornerCutting::Forbid,
+    );
+    let grid = Grid::new(1.0, IVec2::splat(5), 10.0);
+
+    let cell = &ff.grid[2][2];
+    assert!(!cell.line_of_sight);
+    assert_eq!(cell.flow, cell.best_direction.vector().as_vec2());
+
+    // the centre of the grid is the centre of o
+    let sample = ff.sample_flow(Vec3::ZERO, &grid);
+    assert_eq!(sample.direction, cell.flow);
+    assert_eq!(sample.confidence, 1.0);
+}
+
+#[test]
+fn reachability_moves_like_the_integration_mode() {
+    let mut grid = Grid::new(1.0, IVec2::splat(3), 10.0);
+    grid.corner_cutting = CornerCutting::Allow;
+    for idx in DIAGONAL_GAP {
+        grid.set_terrain_cost(idx, Cost::Blocked);
+    }
+
+    let mut ff = FlowField::new(grid.size, Vec::new(), 0.0, Vec3::ZERO);
+    ff.corner_cutting = grid.corner_cutting;
+    ff.create_cost_field(&grid);
+    assert_eq!(
+        ff.reachable_goals(&[IVec2::ZERO], &[IVec2::ONE]),
+        Some(vec![IVec2::ZERO])
+    );
+
+    // the eikonal solver can't squeeze through the diagonal gap, so the closest reachable cell is used instead
+    ff.integration_mode = IntegrationMode::Eikonal;
+    let goals = ff.reachable_goals(&[IVec2::ZERO], &[IVec2::ONE]).unwrap();
+    assert_ne!(goals, vec![IVec2::ZERO]);
+
+    ff.create_integration_field(&grid, &goals);
+    assert_ne!(ff.grid[1][1].best_cost, UNREACHED);
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    components::{CostEffect, CostModifier, ObstacleShape},
+    cost::Cost,
+    grid::Grid,
+};
+
+// the cell under the middle of the grid
+const CELL: IVec2 = IVec2::new(2, 2);
+
+fn apply(grid: &mut Grid, entity: u32, effect: CostEffect) {
+    let modifier = CostModifier::new(ObstacleShape::Rectangle(Vec2::splat(20.0)), effect);
+    grid.update_modifier_cells(
+        Entity::from_raw(entity),
+        &GlobalTransform::default(),
+        &modifier,
+    );
+}
+
+fn remove(grid: &mut Grid, entity: u32) {
+    grid.reset_modifier_cells(vec![Entity::from_raw(entity)]);
+}
+
+fn cost(grid: &Grid) -> Cost {
+    grid.grid[CELL.y as usize][CELL.x as usize].cost
+}
+
+#[test]
+fn removing_overlapping_multipliers_restores_the_layers_exactly() {
+    let mut grid = Grid::new(1.0, IVec2::splat(5), 10.0);
+    let untouched = grid.cost_layers[CELL.y as usize][CELL.x as usize];
+
+    let factors = [3.0, 0.7, 1.3, 0.1, 2.9];
+    for (i, factor) in factors.iter().enumerate() {
+        apply(&mut grid, i as u32, CostEffect::Multiply(*factor));
+    }
+
+    // remove them in a different order than they were added
+    for i in [3, 0, 4, 1, 2] {
+        remove(&mut grid, i);
+    }
+
+    assert_eq!(
+        grid.cost_layers[CELL.y as usize][CELL.x as usize],
+        untouched
+    );
+    assert_eq!(cost(&grid), Cost::ONE);
+}
+
+#[test]
+fn multipliers_are_applied_in_the_same_order_whatever_order_they_were_added_in() {
+    let mut a = Grid::new(1.0, IVec2::splat(5), 10.0);
+    let mut b = Grid::new(1.0, IVec2::splat(5), 10.0);
+
+    let factors = [3.0, 0.7, 1.3, 0.1, 2.9];
+    for (i, factor) in factors.iter().enumerate() {
+        apply(&mut a, i as u32, CostEffect::Multiply(*factor));
+    }
+    for (i, factor) in factors.iter().enumerate().rev() {
+        apply(&mut b, i as u32, CostEffect::Multiply(*factor));
+    }
+
+    assert_eq!(a.cost_layers, b.cost_layers);
+}
+
+#[test]
+fn removing_an_amount_is_exact_after_clamping() {
+    let mut grid = Grid::new(1.0, IVec2::splat(5), 10.0);
+
+    apply(&mut grid, 0, CostEffect::Add(i32::MAX));
+    apply(&mut grid, 1, CostEffect::Add(i32::MAX));
+    apply(&mut grid, 2, CostEffect::Add(-5));
+    assert_eq!(cost(&grid), Cost::MAX);
+
+    remove(&mut grid, 0);
+    remove(&mut grid, 1);
+    assert_eq!(cost(&grid), Cost::Walkable(Cost::SCALE - 5));
+
+    remove(&mut grid, 2);
+    assert_eq!(cost(&grid), Cost::ONE);
+}
+
+#[test]
+fn manual_amounts_survive_modifiers() {
+    let mut grid = Grid::new(1.0, IVec2::splat(5), 10.0);
+    grid.add_cost_modifier(CELL, 7);
+
+    apply(&mut grid, 0, CostEffect::Multiply(2.0));
+    apply(&mut grid, 1, CostEffect::Add(3));
+    assert_eq!(cost(&grid), Cost::Walkable(Cost::SCALE * 2 + 7 + 3));
+
+    remove(&mut grid, 0);
+    remove(&mut grid, 1);
+    assert_eq!(cost(&grid), Cost::Walkable(Cost::SCALE + 7));
+}
@@ -0,0 +1,40 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{cost::Cost, flowfield::FlowField};
+
+mod common;
+use common::{costs, fifo_integration_field, weighted_grid};
+
+#[test]
+fn dijkstra_matches_the_fifo_reference() {
+    let cases = [
+        (IVec2::new(16, 16), vec![IVec2::new(8, 8)], 0x9E37_79B9),
+        (IVec2::new(40, 24), vec![IVec2::new(0, 0)], 0x1234_5678),
+        (IVec2::new(33, 47), vec![IVec2::new(32, 46)], 0xDEAD_BEEF),
+        (IVec2::new(64, 64), vec![IVec2::new(10, 50)], 0x0BAD_F00D),
+        (
+            IVec2::new(48, 32),
+            vec![IVec2::new(5, 5), IVec2::new(6, 5), IVec2::new(40, 28)],
+            0xC0FF_EE00,
+        ),
+    ];
+
+    for (size, goals, seed) in cases {
+        let mut grid = weighted_grid(size, seed);
+        for goal in goals.iter() {
+            grid.set_terrain_cost(*goal, Cost::ONE);
+        }
+
+        let mut ff = FlowField::new(size, Vec::new(), 0.0, Vec3::ZERO);
+        ff.create_integration_field(&grid, &goals);
+
+        let (reference, _) = fifo_integration_field(&costs(&grid), &goals);
+        for (y, row) in reference.iter().enumerate() {
+            for (x, best_cost) in row.iter().enumerate() {
+                assert_eq!(
+                    ff.grid[y][x].best_cost, *best_cost,
+                    "cell ({x}, {y}) on the {size} grid towards {goals:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    cost::Cost,
+    flowfield::{FlowField, Goal},
+    grid::Grid,
+};
+
+#[test]
+fn duplicate_goal_cells_are_dropped_in_order() {
+    let grid = Grid::new(1.0, IVec2::splat(10), 10.0);
+    let goal = Goal::Cells(vec![
+        IVec2::new(3, 3),
+        IVec2::new(1, 2),
+        IVec2::new(3, 3),
+        IVec2::new(1, 2),
+        IVec2::new(0, 0),
+    ]);
+
+    assert_eq!(
+        goal.cells(&grid),
+        vec![IVec2::new(3, 3), IVec2::new(1, 2), IVec2::new(0, 0)]
+    );
+}
+
+#[test]
+fn large_goal_areas_cover_every_cell_once() {
+    let grid = Grid::new(150.0, IVec2::splat(300), 10.0);
+
+    let cells = Goal::Circle {
+        center: Vec3::ZERO,
+        radius: 10000.0,
+    }
+    .cells(&grid);
+    assert_eq!(cells.len(), 300 * 300);
+    assert_eq!(cells[0], IVec2::splat(150));
+}
+
+#[test]
+fn unreachable_goals_fall_back_to_the_closest_reachable_cell() {
+    // . . . . . . . . . .
+    // . . . . # # # # # .
+    // . . . . # . . . # .
+    // . . . . # . g g # .
+    // . . . . # . . . # .
+    // . . . . # # # # # .
+    // goals walled in on the right, closest to the right side of the wall
+    let mut grid = Grid::new(1.0, IVec2::new(10, 6), 10.0);
+    for x in 4..9 {
+        grid.set_terrain_cost(IVec2::new(x, 1), Cost::Blocked);
+        grid.set_terrain_cost(IVec2::new(x, 5), Cost::Blocked);
+    }
+    for y in 1..6 {
+        grid.set_terrain_cost(IVec2::new(4, y), Cost::Blocked);
+        grid.set_terrain_cost(IVec2::new(8, y), Cost::Blocked);
+    }
+    let goals = vec![IVec2::new(6, 3), IVec2::new(7, 3)];
+
+    let mut ff = FlowField::new(grid.size, Vec::new(), 0.0, Vec3::ZERO);
+    ff.create_cost_field(&grid);
+
+    assert_eq!(
+        ff.reachable_goals(&goals, &[IVec2::new(0, 3)]),
+        Some(vec![IVec2::new(9, 3)])
+    );
+}
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    components::{Obstacle, ObstacleShape},
+    cost::Cost,
+    grid::Grid,
+};
+
+fn cost(grid: &Grid, idx: IVec2) -> Cost {
+    grid.grid[idx.y as usize][idx.x as usize].cost
+}
+
+// a wall much thinner than a cell, centered on column 5 and covering rows 4 to 6
+#[test]
+fn thin_obstacle_without_padding_only_raises_costs() {
+    let mut grid = Grid::new(1.0, IVec2::new(10, 10), 10.0);
+    grid.coverage_threshold = 0.5;
+
+    let obstacle = Obstacle {
+        falloff: 10.0,
+        falloff_cost: 20,
+        ..Obstacle::new(ObstacleShape::Rectangle(Vec2::new(2.0, 30.0)))
+    };
+    let transform = GlobalTransform::from_translation(Vec3::new(5.0, 0.0, 5.0));
+    assert!(grid.update_cell_costs(Entity::from_raw(1), &transform, &obstacle));
+
+    // the centers of the cells under the wall are inside it, but the wall covers too little of them to block them
+    for y in 4..=6 {
+        assert_eq!(cost(&grid, IVec2::new(5, y)), Cost::Walkable(30));
+    }
+
+    // the falloff fades out from the wall
+    assert_eq!(cost(&grid, IVec2::new(4, 5)), Cost::Walkable(12));
+    assert_eq!(cost(&grid, IVec2::new(5, 7)), Cost::Walkable(20));
+    assert_eq!(cost(&grid, IVec2::new(3, 5)), Cost::ONE);
+
+    for row in grid.grid.iter() {
+        assert!(row.iter().all(|cell| !cell.cost.is_blocked()));
+    }
+}
+
+#[test]
+fn padding_blocks_the_cells_around_a_thin_obstacle() {
+    let mut grid = Grid::new(1.0, IVec2::new(10, 10), 10.0);
+    grid.coverage_threshold = 0.5;
+
+    let obstacle = Obstacle {
+        padding: 1.0,
+        ..Obstacle::new(ObstacleShape::Rectangle(Vec2::new(2.0, 30.0)))
+    };
+    let transform = GlobalTransform::from_translation(Vec3::new(5.0, 0.0, 5.0));
+    grid.update_cell_costs(Entity::from_raw(1), &transform, &obstacle);
+
+    for y in 4..=6 {
+        assert_eq!(cost(&grid, IVec2::new(5, y)), Cost::Blocked);
+    }
+    assert_eq!(cost(&grid, IVec2::new(4, 5)), Cost::ONE);
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    components::{Obstacle, ObstacleShape},
+    cost::Cost,
+    grid::{CornerCutting, Grid},
+};
+
+mod common;
+use common::Rng;
+
+fn pos(grid: &Grid, idx: IVec2) -> Vec3 {
+    grid.grid[idx.y as usize][idx.x as usize].world_pos
+}
+
+// true if both labelings split the cells into the same regions, whatever their labels are
+fn same_regions(a: &[Vec<Option<u32>>], b: &[Vec<Option<u32>>]) -> bool {
+    let mut a_to_b = HashMap::new();
+    let mut b_to_a = HashMap::new();
+
+    a.iter()
+        .flatten()
+        .zip(b.iter().flatten())
+        .all(|(a_region, b_region)| match (a_region, b_region) {
+            (None, None) => true,
+            (Some(a_region), Some(b_region)) => {
+                *a_to_b.entry(*a_region).or_insert(*b_region) == *b_region
+                    && *b_to_a.entry(*b_region).or_insert(*a_region) == *a_region
+            }
+            _ => false,
+        })
+}
+
+fn assert_matches_full_relabel(grid: &mut Grid) {
+    let updated = grid.regions.clone();
+    grid.relabel_regions();
+    assert!(same_regions(&updated, &grid.regions));
+}
+
+// . . . # . . .
+// . . . # . . .
+// . . . g . . .
+// . . . # . . .
+// . . . # . . .
+// a wall down the middle, with a gap at g
+fn walled_grid() -> Grid {
+    let mut grid = Grid::new(1.0, IVec2::new(7, 5), 10.0);
+    for y in [0, 1, 3, 4] {
+        grid.set_terrain_cost(IVec2::new(3, y), Cost::Blocked);
+    }
+    grid.update_regions();
+    grid
+}
+
+#[test]
+fn blocking_a_gap_splits_the_region() {
+    let mut grid = walled_grid();
+    let (left, right) = (pos(&grid, IVec2::new(0, 2)), pos(&grid, IVec2::new(6, 2)));
+    assert!(grid.is_reachable(left, right));
+
+    grid.set_terrain_cost(IVec2::new(3, 2), Cost::Blocked);
+    grid.update_regions();
+
+    assert!(!grid.is_reachable(left, right));
+    assert!(grid.is_reachable(left, pos(&grid, IVec2::new(2, 4))));
+    assert_eq!(grid.region_of(pos(&grid, IVec2::new(3, 2))), None);
+    assert_matches_full_relabel(&mut grid);
+}
+
+#[test]
+fn unblocking_a_gap_merges_the_regions() {
+    let mut grid = walled_grid();
+    grid.set_terrain_cost(IVec2::new(3, 2), Cost::Blocked);
+    grid.update_regions();
+
+    let (left, right) = (pos(&grid, IVec2::new(0, 0)), pos(&grid, IVec2::new(6, 4)));
+    assert!(!grid.is_reachable(left, right));
+
+    grid.set_terrain_cost(IVec2::new(3, 2), Cost::ONE);
+    grid.update_regions();
+
+    assert!(grid.is_reachable(left, right));
+    assert!(grid.is_reachable(left, pos(&grid, IVec2::new(3, 2))));
+    assert_matches_full_relabel(&mut grid);
+}
+
+#[test]
+fn diagonal_gaps_follow_the_corner_cutting_policy() {
+    // . #
+    // # .
+    let mut grid = Grid::new(1.0, IVec2::new(2, 2), 10.0);
+    grid.set_terrain_cost(IVec2::new(1, 0), Cost::Blocked);
+    grid.set_terrain_cost(IVec2::new(0, 1), Cost::Blocked);
+    grid.update_regions();
+
+    let (a, b) = (pos(&grid, IVec2::ZERO), pos(&grid, IVec2::ONE));
+    assert!(!grid.is_reachable(a, b));
+
+    grid.corner_cutting = CornerCutting::Allow;
+    grid.relabel_regions();
+    assert!(grid.is_reachable(a, b));
+}
+
+#[test]
+fn incremental_updates_match_a_full_relabel() {
+    for policy in [
+        CornerCutting::Forbid,
+        CornerCutting::AllowIfOneFree,
+        CornerCutting::Allow,
+    ] {
+        // one grid is updated incrementally, the other is relabeled from scratch after every change
+        let mut grids = [
+            Grid::new(1.0, IVec2::new(24, 16), 10.0),
+            Grid::new(1.0, IVec2::new(24, 16), 10.0),
+        ];
+        for grid in grids.iter_mut() {
+            grid.corner_cutting = policy;
+            grid.relabel_regions();
+        }
+
+        let mut rng = Rng(0x9E37_79B9);
+        for _ in 0..60 {
+            // block or unblock a handful of cells, blocking twice as often as unblocking
+            for _ in 0..8 {
+                let idx = IVec2::new(rng.below(24) as i32, rng.below(16) as i32);
+                let cost = match rng.below(3) {
+                    0 => Cost::ONE,
+                    _ => Cost::Blocked,
+                };
+                for grid in grids.iter_mut() {
+                    grid.set_terrain_cost(idx, cost);
+                }
+            }
+
+            let [updated, relabeled] = &mut grids;
+            updated.update_regions();
+            relabeled.relabel_regions();
+            assert!(same_regions(&updated.regions, &relabeled.regions));
+        }
+    }
+}
+
+#[test]
+fn a_moving_obstacle_splits_and_merges_regions_like_a_full_relabel() {
+    // a wall across the middle with a gap, which the obstacle closes on its way past
+    let mut grids = [
+        Grid::new(1.0, IVec2::new(24, 16), 10.0),
+        Grid::new(1.0, IVec2::new(24, 16), 10.0),
+    ];
+    for grid in grids.iter_mut() {
+        for x in (0..24).filter(|x| !(10..13).contains(x)) {
+            grid.set_terrain_cost(IVec2::new(x, 8), Cost::Blocked);
+        }
+        grid.relabel_regions();
+    }
+
+    let obstacle = Obstacle::new(ObstacleShape::Rectangle(Vec2::new(40.0, 30.0)));
+    let mut split = false;
+    for step in 0..24 {
+        let position = Vec3::new(-120.0 + step as f32 * 10.0, 0.0, 5.0);
+        for grid in grids.iter_mut() {
+            grid.update_cell_costs(
+                Entity::from_raw(0),
+                &GlobalTransform::from_translation(position),
+                &obstacle,
+            );
+        }
+
+        let [updated, relabeled] = &mut grids;
+        updated.update_regions();
+        relabeled.relabel_regions();
+        assert!(same_regions(&updated.regions, &relabeled.regions));
+
+        let (top, bottom) = (
+            pos(updated, IVec2::new(0, 0)),
+            pos(updated, IVec2::new(0, 15)),
+        );
+        split |= !updated.is_reachable(top, bottom);
+    }
+    assert!(split);
+}
+
+#[test]
+fn mark_all_dirty_picks_up_direct_edits() {
+    let mut grid = walled_grid();
+    grid.take_dirty_rect();
+    let version = grid.version;
+
+    grid.grid[2][3].cost = Cost::Blocked;
+    grid.mark_all_dirty();
+
+    let (left, right) = (pos(&grid, IVec2::new(0, 2)), pos(&grid, IVec2::new(6, 2)));
+    assert!(!grid.is_reachable(left, right));
+    assert_ne!(grid.version, version);
+    assert_eq!(
+        grid.take_dirty_rect(),
+        Some(IRect::from_corners(IVec2::ZERO, grid.size - IVec2::ONE))
+    );
+}
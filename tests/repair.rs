@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use bevy_pathfinding::{
+    components::{Obstacle, ObstacleShape},
+    cost::Cost,
+    flowfield::FlowField,
+    grid::{CornerCutting, Grid, IntegrationMode},
+    resources::FlowFieldCache,
+};
+
+mod common;
+use common::Rng;
+
+const SIZE: IVec2 = IVec2::new(32, 24);
+const CELL_SIZE: f32 = 10.0;
+
+fn random_cell(rng: &mut Rng) -> IVec2 {
+    IVec2::new(
+        rng.below(SIZE.x as u32) as i32,
+        rng.below(SIZE.y as u32) as i32,
+    )
+}
+
+// changes the terrain cost of a few cells, and moves or reshapes one of the obstacles
+fn edit(grid: &mut Grid, rng: &mut Rng) {
+    for _ in 0..rng.below(4) {
+        let cost = match rng.below(4) {
+            0 => Cost::Blocked,
+            _ => Cost::Walkable(5 + rng.below(60) as u16),
+        };
+        grid.set_terrain_cost(random_cell(rng), cost);
+    }
+
+    let obstacle = Obstacle {
+        padding: rng.below(2) as f32 * 3.0,
+        falloff: rng.below(2) as f32 * 15.0,
+        falloff_cost: 20,
+        ..Obstacle::new(ObstacleShape::Rectangle(Vec2::new(
+            5.0 + rng.below(40) as f32,
+            5.0 + rng.below(40) as f32,
+        )))
+    };
+    let half_extent = SIZE.as_vec2() * CELL_SIZE / 2.0;
+    let position = Vec3::new(
+        rng.below(half_extent.x as u32 * 2) as f32 - half_extent.x,
+        0.0,
+        rng.below(half_extent.y as u32 * 2) as f32 - half_extent.y,
+    );
+    grid.update_cell_costs(
+        Entity::from_raw(rng.below(3)),
+        &GlobalTransform::from_translation(position),
+        &obstacle,
+    );
+}
+
+fn fresh_flowfield(grid: &Grid, settings: &FlowField, goals: &[IVec2]) -> FlowField {
+    let mut ff = FlowField::new(grid.size, Vec::new(), 0.0, Vec3::ZERO);
+    ff.corner_cutting = settings.corner_cutting;
+    ff.integration_mode = settings.integration_mode;
+    ff.unit_radius = settings.unit_radius;
+    ff.create_integration_field(grid, goals);
+    ff.create_flowfield();
+    ff
+}
+
+fn assert_same_fields(repaired: &FlowField, fresh: &FlowField, step: usize) {
+    for (repaired_row, fresh_row) in repaired.grid.iter().zip(fresh.grid.iter()) {
+        for (repaired, fresh) in repaired_row.iter().zip(fresh_row.iter()) {
+            let idx = fresh.idx;
+            assert_eq!(repaired.cost, fresh.cost, "cost at {idx} after edit {step}");
+            assert_eq!(
+                repaired.best_cost, fresh.best_cost,
+                "best_cost at {idx} after edit {step}"
+            );
+            assert_eq!(
+                repaired.best_direction, fresh.best_direction,
+                "best_direction at {idx} after edit {step}"
+            );
+            assert_eq!(repaired.flow, fresh.flow, "flow at {idx} after edit {step}");
+            assert_eq!(
+                repaired.line_of_sight, fresh.line_of_sight,
+                "line_of_sight at {idx} after edit {step}"
+            );
+        }
+    }
+}
+
+#[test]
+fn repaired_fields_match_fresh_fields() {
+    let single_goal = vec![IVec2::new(12, 9)];
+    let goal_area: Vec<IVec2> = (4..7)
+        .flat_map(|y| (20..24).map(move |x| IVec2::new(x, y)))
+        .collect();
+
+    let mut settings = Vec::new();
+    for corner_cutting in [
+        CornerCutting::Forbid,
+        CornerCutting::AllowIfOneFree,
+        CornerCutting::Allow,
+    ] {
+        for unit_radius in [0.0, 8.0] {
+            settings.push(FlowField {
+                corner_cutting,
+                unit_radius,
+                ..default()
+            });
+        }
+    }
+    settings.push(FlowField {
+        integration_mode: IntegrationMode::Eikonal,
+        ..default()
+    });
+
+    for (i, settings) in settings.iter().enumerate() {
+        for goals in [&single_goal, &goal_area] {
+            let mut rng = Rng(0x9E37_79B9 ^ i as u32);
+            let mut grid = Grid::new(1.0, SIZE, CELL_SIZE);
+            grid.corner_cutting = settings.corner_cutting;
+
+            let mut cache = FlowFieldCache::default();
+            let mut ff = fresh_flowfield(&grid, settings, goals);
+            for step in 0..40 {
+                edit(&mut grid, &mut rng);
+
+                // clear the terrain under the goals, so they are rarely blocked
+                for goal in goals.iter() {
+                    grid.set_terrain_cost(*goal, Cost::ONE);
+                }
+                grid.update_clearance();
+
+                if let Some(dirty) = grid.take_dirty_rect() {
+                    ff.repair_fields(&grid, dirty, &mut cache);
+                }
+                assert_same_fields(&ff, &fresh_flowfield(&grid, settings, goals), step);
+            }
+
+            // repaired fields go stale with the next change, so they aren't cached
+            assert!(cache.is_empty());
+        }
+    }
+}